@@ -0,0 +1,30 @@
+//! Compares cloning and dropping an `Arc` with atomic read-modify-write
+//! operations against the unsynchronized single-threaded variants.
+//!
+//! Run with `cargo bench --bench arc_clone`.
+
+use rust_atomics::arc::Arc;
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 10_000_000;
+
+fn main() {
+    let arc = Arc::new(0u64);
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        drop(black_box(arc.clone()));
+    }
+    let atomic = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        // Safety: The `Arc` never leaves this thread.
+        unsafe { Arc::drop_unsync(black_box(Arc::clone_unsync(&arc))) };
+    }
+    let unsync = start.elapsed();
+
+    println!("clone + drop:               {:?}/iter", atomic / ITERATIONS);
+    println!("clone_unsync + drop_unsync: {:?}/iter", unsync / ITERATIONS);
+}
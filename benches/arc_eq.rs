@@ -0,0 +1,33 @@
+//! Compares `==` on two `Arc`s to the same large value, which only compares
+//! the pointers, against `Arc::content_eq`, which compares the values.
+//!
+//! Run with `cargo bench --bench arc_eq`.
+
+use rust_atomics::arc::Arc;
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 1_000;
+
+fn main() {
+    let a = Arc::new(vec![0u8; 1 << 20]);
+    let b = a.clone();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        assert!(black_box(&a) == black_box(&b));
+    }
+    let short_circuit = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        assert!(Arc::content_eq(black_box(&a), black_box(&b)));
+    }
+    let content = start.elapsed();
+
+    println!(
+        "== (same allocation): {:?}/iter",
+        short_circuit / ITERATIONS
+    );
+    println!("content_eq:           {:?}/iter", content / ITERATIONS);
+}
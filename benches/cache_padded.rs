@@ -0,0 +1,36 @@
+//! Compares threads hammering their own `SpinLock` in an array of adjacent
+//! locks against an array of cache padded locks.
+//!
+//! Run with `cargo bench --bench cache_padded`.
+
+use rust_atomics::cache_padded::CachePadded;
+use rust_atomics::spin_lock::SpinLock;
+use std::array;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const THREADS: usize = 4;
+const ITERATIONS: u32 = 1_000_000;
+
+fn run(locks: [&SpinLock<u64>; THREADS]) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for lock in locks {
+            s.spawn(move || {
+                for _ in 0..ITERATIONS {
+                    *lock.lock() += 1;
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn main() {
+    let unpadded: [SpinLock<u64>; THREADS] = array::from_fn(|_| SpinLock::new(0));
+    let padded: [CachePadded<SpinLock<u64>>; THREADS] =
+        array::from_fn(|_| CachePadded::new(SpinLock::new(0)));
+
+    println!("unpadded: {:?}", run(unpadded.each_ref()));
+    println!("padded:   {:?}", run(array::from_fn(|i| &*padded[i])));
+}
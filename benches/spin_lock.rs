@@ -0,0 +1,50 @@
+//! Compares locking and unlocking an uncontended `SpinLock`, which can put
+//! waiting threads to sleep, against a plain spin lock that can't, to check
+//! that the fast path didn't get slower.
+//!
+//! Run with `cargo bench --bench spin_lock`.
+
+use rust_atomics::spin_lock::SpinLock;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+const ITERATIONS: u32 = 10_000_000;
+
+/// The spin lock from chapter 4 of the book, without a guard.
+struct PlainSpinLock(AtomicBool);
+
+impl PlainSpinLock {
+    fn lock(&self) {
+        while self.0.swap(true, Ordering::Acquire) {
+            std::hint::spin_loop();
+        }
+    }
+
+    fn unlock(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+fn main() {
+    let lock = SpinLock::new(0u64);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        *black_box(&lock).lock() += 1;
+    }
+    let parking = start.elapsed();
+
+    let plain = PlainSpinLock(AtomicBool::new(false));
+    let mut value = 0u64;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(&plain).lock();
+        value += 1;
+        black_box(&plain).unlock();
+    }
+    let spinning = start.elapsed();
+    black_box(value);
+
+    println!("SpinLock:       {:?}/iter", parking / ITERATIONS);
+    println!("plain spinlock: {:?}/iter", spinning / ITERATIONS);
+}
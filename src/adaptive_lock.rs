@@ -0,0 +1,149 @@
+use crate::spin_lock::{self, SpinLock};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The contention score from which waiting threads skip spinning altogether.
+const PARK_THRESHOLD: u32 = 8;
+
+/// The highest contention score, which bounds how long it takes to go back to
+/// spinning once contention is over.
+const MAX_CONTENTION: u32 = 2 * PARK_THRESHOLD;
+
+/// A lock that spins while contention is light, but puts waiting threads to
+/// sleep right away under sustained contention, when spinning would just
+/// waste CPU time.
+///
+/// This is a [`SpinLock`], which already goes from spinning to sleeping when
+/// waiting takes too long, with a contention score on top that goes up
+/// whenever the lock is released while threads are sleeping on it, and goes
+/// down whenever it is released without any sleeping threads.
+pub struct AdaptiveLock<T> {
+    inner: SpinLock<T>,
+    contention: AtomicU32,
+}
+
+impl<T> AdaptiveLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: SpinLock::new(value),
+            contention: AtomicU32::new(0),
+        }
+    }
+
+    /// Whether waiting threads currently go to sleep without spinning first.
+    pub fn is_parking(&self) -> bool {
+        self.contention.load(Ordering::Relaxed) >= PARK_THRESHOLD
+    }
+
+    pub fn lock(&self) -> Guard<'_, T> {
+        let guard = if self.is_parking() {
+            self.inner.lock_without_spinning()
+        } else {
+            self.inner.lock()
+        };
+        Guard { lock: self, guard }
+    }
+
+    fn update_contention(&self, f: impl Fn(u32) -> u32) {
+        // The score is only a heuristic, so we don't mind losing an update
+        // now and then, as long as the common case stays cheap.
+        let n = self.contention.load(Ordering::Relaxed);
+        if f(n) != n {
+            self.contention.store(f(n), Ordering::Relaxed);
+        }
+    }
+}
+
+pub struct Guard<'a, T> {
+    lock: &'a AdaptiveLock<T>,
+    guard: spin_lock::Guard<'a, T>,
+}
+
+impl<T> Deref for Guard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for Guard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for Guard<'_, T> {
+    fn drop(&mut self) {
+        // The inner guard only unlocks after this, so the sleepers we see are
+        // the ones this unlock is going to wake.
+        if self.lock.inner.has_sleepers() {
+            // Spinning didn't help the waiters (or was skipped already).
+            self.lock.update_contention(|n| (n + 1).min(MAX_CONTENTION));
+        } else {
+            self.lock.update_contention(|n| n.saturating_sub(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adaptive_lock::AdaptiveLock;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn parks_under_sustained_contention() {
+        let lock = AdaptiveLock::new(0);
+        let saw_parking = AtomicBool::new(false);
+        let saw_sleeper = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..25 {
+                        let mut g = lock.lock();
+                        *g += 1;
+                        // Holding the lock for long makes spinning useless.
+                        thread::sleep(Duration::from_millis(1));
+                        if lock.is_parking() {
+                            saw_parking.store(true, Ordering::Relaxed);
+                        }
+                        if lock.inner.has_sleepers() {
+                            saw_sleeper.store(true, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        assert!(saw_parking.load(Ordering::Relaxed));
+        assert!(saw_sleeper.load(Ordering::Relaxed));
+        assert_eq!(*lock.lock(), 100);
+    }
+
+    #[test]
+    fn spins_under_light_contention() {
+        let lock = AdaptiveLock::new(0);
+        let saw_parking = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        *lock.lock() += 1;
+                        if lock.is_parking() {
+                            saw_parking.store(true, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        // Short critical sections never make spinning useless.
+        assert!(!saw_parking.load(Ordering::Relaxed));
+        // Uncontended acquisitions bring the score back down.
+        for _ in 0..100 {
+            drop(lock.lock());
+        }
+        assert!(!lock.is_parking());
+        assert_eq!(*lock.lock(), 20_000);
+    }
+}
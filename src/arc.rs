@@ -1,10 +1,29 @@
-use std::cell::UnsafeCell;
-use std::mem::ManuallyDrop;
-use std::ops::Deref;
-use std::ptr::NonNull;
-use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use crate::sync::atomic::{fence, AtomicUsize, Ordering};
+use crate::sync::hint;
+use ::alloc::alloc::{self, Layout};
+use ::alloc::boxed::Box;
+use ::alloc::vec;
+use ::alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cell::UnsafeCell;
+use core::error::Error;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+#[cfg(feature = "nightly")]
+use core::marker::Unsize;
+use core::mem::{self, ManuallyDrop, MaybeUninit};
+#[cfg(feature = "nightly")]
+use core::ops::CoerceUnsized;
+use core::ops::Deref;
+use core::pin::Pin;
+use core::ptr::{self, NonNull};
+#[cfg(feature = "trace")]
+use core::sync::atomic::AtomicPtr;
 
-struct ArcData<T> {
+/// `repr(C)` keeps the counters in front and the data last, so that the
+/// layout of an unsized `ArcData` can be computed by hand.
+#[repr(C)]
+struct ArcData<T: ?Sized> {
     /// Number of `Arc`s.
     data_ref_count: AtomicUsize,
     /// Number of `Arc`s and `Weak`s combined.
@@ -13,26 +32,31 @@ struct ArcData<T> {
     data: UnsafeCell<ManuallyDrop<T>>,
 }
 
-pub struct Weak<T> {
+pub struct Weak<T: ?Sized> {
     ptr: NonNull<ArcData<T>>,
 }
 
-unsafe impl<T: Send + Sync> Send for Weak<T> {}
-unsafe impl<T: Send + Sync> Sync for Weak<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Send for Weak<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for Weak<T> {}
 
-impl<T> Weak<T> {
+impl<T: ?Sized> Weak<T> {
     fn data(&self) -> &ArcData<T> {
         unsafe { self.ptr.as_ref() }
     }
 
     pub fn upgrade(&self) -> Option<Arc<T>> {
         let mut n = self.data().data_ref_count.load(Ordering::Relaxed);
+        let mut failures = 0;
         loop {
             if n == 0 {
                 return None;
             }
 
-            assert!(n < usize::MAX);
+            // Same policy as `Arc::clone`: leaking upgraded `Arc`s must never
+            // bring the count anywhere near overflowing.
+            if n > Arc::<T>::MAX_REFCOUNT {
+                abort();
+            }
             if let Err(e) = self.data().data_ref_count.compare_exchange_weak(
                 n,
                 n + 1,
@@ -40,24 +64,68 @@ impl<T> Weak<T> {
                 Ordering::Relaxed,
             ) {
                 n = e;
+                backoff(&mut failures);
                 continue;
             }
 
+            #[cfg(feature = "trace")]
+            trace(ArcEvent::Upgrade, self.ptr);
             return Some(Arc { ptr: self.ptr });
         }
     }
+
+    /// Returns whether both `Weak`s point to the same allocation, even if the
+    /// data has already been dropped.
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        ptr::addr_eq(a.ptr.as_ptr(), b.ptr.as_ptr())
+    }
+
+    /// Returns a pointer to where the data is, or was.
+    ///
+    /// The pointer may only be dereferenced while an `Arc` keeps the data
+    /// alive, but can always be compared, for example against
+    /// [`Arc::as_ptr`].
+    pub fn as_ptr(&self) -> *const T {
+        // Safety: The allocation is alive as long as `self` is. No reference
+        // to the possibly dropped data is created.
+        unsafe { UnsafeCell::raw_get(ptr::addr_of!((*self.ptr.as_ptr()).data)) as *const T }
+    }
+
+    /// Returns the number of `Arc`s pointing to the data, which is 0 once the
+    /// data has been dropped.
+    ///
+    /// This is only a snapshot, which may already be outdated when it returns.
+    pub fn strong_count(&self) -> usize {
+        self.data().data_ref_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of `Weak`s pointing to the allocation, including
+    /// this one.
+    ///
+    /// This is only a snapshot, which may already be outdated when it returns.
+    pub fn weak_count(&self) -> usize {
+        // `Arc::get_mut` only temporarily sets this to usize::MAX when there
+        // are no `Weak`s, so we can't observe that while `self` exists.
+        let n = self.data().alloc_ref_count.load(Ordering::Relaxed);
+        // All `Arc`s together hold one extra count.
+        if self.strong_count() > 0 {
+            n.saturating_sub(1).max(1)
+        } else {
+            n
+        }
+    }
 }
 
-impl<T> Clone for Weak<T> {
+impl<T: ?Sized> Clone for Weak<T> {
     fn clone(&self) -> Self {
-        if self.data().alloc_ref_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
-            std::process::abort();
+        if self.data().alloc_ref_count.fetch_add(1, Ordering::Relaxed) > Arc::<T>::MAX_REFCOUNT {
+            abort();
         }
         Weak { ptr: self.ptr }
     }
 }
 
-impl<T> Drop for Weak<T> {
+impl<T: ?Sized> Drop for Weak<T> {
     fn drop(&mut self) {
         if self.data().alloc_ref_count.fetch_sub(1, Ordering::Release) == 1 {
             fence(Ordering::Acquire);
@@ -68,28 +136,247 @@ impl<T> Drop for Weak<T> {
     }
 }
 
-pub struct Arc<T> {
+pub struct Arc<T: ?Sized> {
     ptr: NonNull<ArcData<T>>,
 }
 
-unsafe impl<T: Send + Sync> Send for Arc<T> {}
-unsafe impl<T: Send + Sync> Sync for Arc<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Send for Arc<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for Arc<T> {}
 
 impl<T> Arc<T> {
     pub fn new(data: T) -> Self {
-        Arc {
-            ptr: NonNull::from(Box::leak(Box::new(ArcData {
+        let ptr = NonNull::from(Box::leak(Box::new(ArcData {
+            data_ref_count: AtomicUsize::new(1),
+            alloc_ref_count: AtomicUsize::new(1),
+            data: UnsafeCell::new(ManuallyDrop::new(data)),
+        })));
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::New, ptr);
+        Arc { ptr }
+    }
+
+    /// Like `new`, but returns an error instead of aborting if the memory
+    /// can't be allocated.
+    pub fn try_new(data: T) -> Result<Self, AllocError> {
+        let layout = Layout::new::<ArcData<T>>();
+        // Safety: The layout is never zero-sized, because of the counters.
+        let ptr = unsafe { alloc::alloc(layout) } as *mut ArcData<T>;
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        unsafe {
+            ptr.as_ptr().write(ArcData {
                 data_ref_count: AtomicUsize::new(1),
                 alloc_ref_count: AtomicUsize::new(1),
                 data: UnsafeCell::new(ManuallyDrop::new(data)),
-            }))),
+            })
+        };
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::New, ptr);
+        Ok(Arc { ptr })
+    }
+
+    /// Allocates an `Arc` for data that is written afterwards, in place.
+    ///
+    /// This avoids building large values on the stack first. Write the data
+    /// through [`Arc::get_mut`], then call [`Arc::assume_init`].
+    pub fn new_uninit() -> Arc<MaybeUninit<T>> {
+        Self::allocate_uninit(alloc::alloc)
+    }
+
+    /// Like [`Arc::new_uninit`], but with the data's memory zeroed.
+    ///
+    /// Calling [`Arc::assume_init`] right away is only sound if all zeros is a
+    /// valid value of `T`, like for integers or atomics.
+    pub fn new_zeroed() -> Arc<MaybeUninit<T>> {
+        Self::allocate_uninit(alloc::alloc_zeroed)
+    }
+
+    /// Allocates an `ArcData<MaybeUninit<T>>` with `allocate`, with both
+    /// counters set to one.
+    fn allocate_uninit(allocate: unsafe fn(Layout) -> *mut u8) -> Arc<MaybeUninit<T>> {
+        let layout = Layout::new::<ArcData<MaybeUninit<T>>>();
+        // Safety: The layout is never zero-sized, because of the counters.
+        let ptr = unsafe { allocate(layout) } as *mut ArcData<MaybeUninit<T>>;
+        if ptr.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        // The data may stay uninitialized, since `MaybeUninit` allows that.
+        unsafe {
+            ptr::addr_of_mut!((*ptr).data_ref_count).write(AtomicUsize::new(1));
+            ptr::addr_of_mut!((*ptr).alloc_ref_count).write(AtomicUsize::new(1));
+            let ptr = NonNull::new_unchecked(ptr);
+            #[cfg(feature = "trace")]
+            trace(ArcEvent::New, ptr);
+            Arc { ptr }
         }
     }
 
+    /// Returns the data if this was the last `Arc`, and otherwise just drops
+    /// this one and returns `None`.
+    ///
+    /// Unlike checking the count first and taking the data afterwards, this
+    /// can't race with other `Arc`s being dropped at the same time: when the
+    /// last two are passed to `into_inner` concurrently, exactly one of them
+    /// gets the data.
+    pub fn into_inner(arc: Self) -> Option<T> {
+        let arc = ManuallyDrop::new(arc);
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::Drop, arc.ptr);
+        if arc.data().data_ref_count.fetch_sub(1, Ordering::Release) != 1 {
+            return None;
+        }
+        fence(Ordering::Acquire);
+        // Safety: The data reference counter is zero, so nothing else will
+        // access the data anymore, and `Drop` won't run for this `Arc`.
+        let data = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+        // Drop the implicit weak pointer that represented all `Arc<T>`s.
+        drop(Weak { ptr: arc.ptr });
+        Some(data)
+    }
+
+    /// Returns the data if this is the only `Arc`, and otherwise gives the
+    /// `Arc` back.
+    ///
+    /// Unlike [`Arc::into_inner`], this never drops the `Arc`, so the caller
+    /// can try again later, but when the last two are passed to `try_unwrap`
+    /// concurrently, both may fail.
+    pub fn try_unwrap(arc: Self) -> Result<T, Self> {
+        // Acquire, so dropping the other `Arc`s happens before we take the
+        // data, like in `drop`. A weak pointer can't upgrade once the count is
+        // zero.
+        if arc
+            .data()
+            .data_ref_count
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(arc);
+        }
+        let arc = ManuallyDrop::new(arc);
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::Drop, arc.ptr);
+        // Safety: Same as in `into_inner`.
+        let data = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+        drop(Weak { ptr: arc.ptr });
+        Ok(data)
+    }
+
+    /// Turns the `Arc` into a pointer to a part of the data, selected by `f`,
+    /// which keeps the whole data alive.
+    pub fn project<U: ?Sized>(arc: Self, f: impl FnOnce(&T) -> &U) -> ProjectedArc<U>
+    where
+        T: Send + Sync + 'static,
+    {
+        let ptr = NonNull::from(f(&arc));
+        // Safety: The pointer comes from `Arc::into_raw`, only unsized to a
+        // trait object, which doesn't change the layout of the `ArcData`.
+        let owner = unsafe { Arc::from_raw(Arc::into_raw(arc) as *const dyn Owner) };
+        ProjectedArc { owner, ptr }
+    }
+
+    /// Creates a pinned `Arc`, for data that must not be moved.
+    pub fn pin(data: T) -> Pin<Arc<T>> {
+        // Safety: The data stays in its allocation until it is dropped in
+        // place, and `Pin` doesn't give out the `&mut Arc` that `get_mut`
+        // would need.
+        unsafe { Pin::new_unchecked(Arc::new(data)) }
+    }
+}
+
+impl<T: ?Sized> Arc<T> {
+    /// The most `Arc`s, or `Weak`s, that may point to the same data. Creating
+    /// another one once there are more than this aborts the process, since
+    /// only leaking them in a loop can get there, and the count must never
+    /// overflow. The margin to `usize::MAX` leaves room for racing threads
+    /// that will all see the count above this and abort.
+    pub const MAX_REFCOUNT: usize = usize::MAX / 2;
+
     fn data(&self) -> &ArcData<T> {
         unsafe { self.ptr.as_ref() }
     }
 
+    /// Returns whether both `Arc`s point to the same allocation.
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        ptr::addr_eq(a.ptr.as_ptr(), b.ptr.as_ptr())
+    }
+
+    /// Compares the values, even if both `Arc`s point to the same allocation,
+    /// unlike `==`.
+    pub fn content_eq(a: &Self, b: &Self) -> bool
+    where
+        T: PartialEq,
+    {
+        **a == **b
+    }
+
+    /// Returns a pointer to the data, without affecting the reference count.
+    pub fn as_ptr(arc: &Self) -> *const T {
+        arc.data().data.get() as *const T
+    }
+
+    /// Consumes the `Arc` without decrementing the reference count, and
+    /// returns a pointer to the data.
+    ///
+    /// Use [`Arc::from_raw`] to turn the pointer back into an `Arc`.
+    pub fn into_raw(arc: Self) -> *const T {
+        Self::as_ptr(&ManuallyDrop::new(arc))
+    }
+
+    /// Reconstructs an `Arc` from a pointer returned by [`Arc::into_raw`].
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Arc::into_raw`, and the strong reference it
+    /// represents must not have been given back already.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        // Safety: The data is alive, so we may look at its alignment.
+        let offset = data_offset(mem::align_of_val(unsafe { &*ptr }));
+        // Safety: The data lives inside an `ArcData`, at this offset.
+        let ptr = unsafe { ptr.byte_sub(offset) } as *mut ArcData<T>;
+        Arc {
+            ptr: unsafe { NonNull::new_unchecked(ptr) },
+        }
+    }
+
+    /// Increments the strong reference count of the `Arc` that `ptr` was
+    /// obtained from, like cloning it would.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from [`Arc::into_raw`], and its `Arc` must still be
+    /// alive, i.e. the strong count must be at least one.
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        let arc = ManuallyDrop::new(unsafe { Self::from_raw(ptr) });
+        // Cloning increments the count with the same overflow protection.
+        let _clone: ManuallyDrop<Self> = arc.clone();
+    }
+
+    /// Decrements the strong reference count of the `Arc` that `ptr` was
+    /// obtained from, like dropping it would. If this was the last one, the
+    /// data is dropped.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from [`Arc::into_raw`], and it must represent a strong
+    /// reference that wasn't given back yet.
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(unsafe { Self::from_raw(ptr) });
+    }
+
+    /// Returns a mutable reference to the data, if this is the only `Arc` and
+    /// there are no `Weak`s.
+    ///
+    /// Any `Weak` makes this return `None`, even one that will never be
+    /// upgraded again: as long as it exists, it could be, and there's no way
+    /// to tell the difference. Only once the `Weak`s are dropped does this
+    /// succeed again. [`Weak::weak_count`] can help to find out which of the
+    /// two conditions failed.
+    ///
+    /// This also means no `Weak` can upgrade between checking the weak count
+    /// and the strong count: locking the weak count only works while there
+    /// are no `Weak`s, and `downgrade` can't create one until it's unlocked.
+    /// A `Weak` that was upgraded and then dropped right before is no problem
+    /// either. Its upgrade comes before its drop, which comes before we lock
+    /// the weak count, so the strong count we see includes the upgrade.
     pub fn get_mut(arc: &mut Self) -> Option<&mut T> {
         // Acquire matches Weak::drop's Release decrement, to make sure any
         // upgraded pointers are visible in the next data_ref_count.load.
@@ -117,16 +404,56 @@ impl<T> Arc<T> {
         unsafe { Some(&mut *arc.data().data.get()) }
     }
 
+    /// Like `get_mut`, but for a pinned `Arc`, so the data stays pinned. This
+    /// allows polling a future behind a `Pin<Arc<_>>` while it isn't shared.
+    ///
+    /// This takes `&mut Pin<Arc<T>>` rather than `Pin<&mut Arc<T>>`: `Arc` is
+    /// `Unpin`, so pinning a reference to it says nothing about its data.
+    pub fn get_pin_mut(arc: &mut Pin<Self>) -> Option<Pin<&mut T>> {
+        // Safety: `Pin` is a transparent wrapper, and the `&mut Arc` is only
+        // used to check for uniqueness, never to move the data or the `Arc`.
+        let arc = unsafe { &mut *(arc as *mut Pin<Self>).cast::<Self>() };
+        // Safety: The data was pinned, and is pinned again before anyone gets
+        // to move it.
+        Arc::get_mut(arc).map(|data| unsafe { Pin::new_unchecked(data) })
+    }
+
+    /// Returns a mutable reference to the data, without checking that this is
+    /// the only `Arc`.
+    ///
+    /// # Safety
+    ///
+    /// For as long as the returned reference is used, no other `Arc` or `Weak`
+    /// pointing to the same data may be dereferenced or upgraded. It's easiest
+    /// to only call this while no other `Arc`s exist, and no `Weak`s that could
+    /// be upgraded. Anything else is undefined behavior, as it would create an
+    /// `&mut T` aliasing an `&T`.
+    pub unsafe fn get_mut_unchecked(arc: &mut Self) -> &mut T {
+        unsafe { &mut *arc.data().data.get() }
+    }
+
     pub fn downgrade(arc: &Self) -> Weak<T> {
+        loop {
+            if let Some(weak) = Self::try_downgrade(arc) {
+                return weak;
+            }
+            hint::spin_loop();
+        }
+    }
+
+    /// Like `downgrade`, but returns `None` instead of spinning while another
+    /// thread is in the middle of `get_mut`.
+    pub fn try_downgrade(arc: &Self) -> Option<Weak<T>> {
         let mut n = arc.data().alloc_ref_count.load(Ordering::Relaxed);
         loop {
             if n == usize::MAX {
-                std::hint::spin_loop();
-                n = arc.data().alloc_ref_count.load(Ordering::Relaxed);
-                continue;
+                return None;
             }
 
-            assert!(n < usize::MAX - 1);
+            // Same policy as `Weak::clone`.
+            if n > Self::MAX_REFCOUNT {
+                abort();
+            }
             // Acquire synchronises with get_mut's release-store.
             if let Err(e) = arc.data().alloc_ref_count.compare_exchange_weak(
                 n,
@@ -137,12 +464,594 @@ impl<T> Arc<T> {
                 n = e;
                 continue;
             }
-            return Weak { ptr: arc.ptr };
+            #[cfg(feature = "trace")]
+            trace(ArcEvent::Downgrade, arc.ptr);
+            return Some(Weak { ptr: arc.ptr });
+        }
+    }
+
+    /// Like `downgrade`, but also returns the number of `Arc`s at the moment
+    /// the `Weak` was created.
+    ///
+    /// The two counters can't be updated together, so the strong count is read
+    /// right after the weak count was bumped. It was exact at some point in
+    /// between, but other threads may clone, drop or upgrade at any time, so
+    /// it's only a snapshot. Since `arc` itself is counted, it's never 0. If
+    /// it's 1, `arc` was the only `Arc`, and any other can only show up by
+    /// upgrading a `Weak`.
+    pub fn downgrade_with_strong_count(arc: &Self) -> (Weak<T>, usize) {
+        let weak = Self::downgrade(arc);
+        let count = arc.data().data_ref_count.load(Ordering::Relaxed);
+        (weak, count)
+    }
+
+    /// Creates `count` `Weak`s at once, with a single update of the weak count
+    /// instead of one per `Weak`.
+    pub fn downgrade_n(arc: &Self, count: usize) -> Vec<Weak<T>> {
+        if count == 0 {
+            return Vec::new();
+        }
+        let mut n = arc.data().alloc_ref_count.load(Ordering::Relaxed);
+        loop {
+            // A compare-and-exchange rather than a `fetch_add`, since the
+            // count must not be touched while locked by `get_mut`.
+            if n == usize::MAX {
+                hint::spin_loop();
+                n = arc.data().alloc_ref_count.load(Ordering::Relaxed);
+                continue;
+            }
+
+            assert!(count <= Self::MAX_REFCOUNT.saturating_sub(n));
+            // Acquire synchronises with get_mut's release-store.
+            match arc.data().alloc_ref_count.compare_exchange_weak(
+                n,
+                n + count,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(e) => n = e,
+            }
+        }
+        (0..count)
+            .map(|_| {
+                #[cfg(feature = "trace")]
+                trace(ArcEvent::Downgrade, arc.ptr);
+                Weak { ptr: arc.ptr }
+            })
+            .collect()
+    }
+
+    /// Like `downgrade`, but borrows `arc` instead of bumping the weak count.
+    /// Useful for handing out something that can be upgraded, without paying
+    /// for creating and dropping a `Weak`.
+    pub fn as_weak_ref(arc: &Self) -> WeakRef<'_, T> {
+        WeakRef { arc }
+    }
+
+    /// Returns the address of the allocation, which identifies the data
+    /// independently of its value.
+    pub fn as_ptr_usize(arc: &Self) -> usize {
+        arc.ptr.as_ptr() as *const () as usize
+    }
+
+    /// Like `clone`, but updates the count with a plain load and store instead
+    /// of an atomic read-modify-write operation, which is noticeably cheaper.
+    ///
+    /// # Safety
+    ///
+    /// Every other access to the counts of the same allocation must
+    /// happen-before or happen-after this call, for example because all
+    /// `Arc`s and `Weak`s pointing to it are only ever used by the current
+    /// thread. That includes everything that creates, drops or inspects a
+    /// reference:
+    ///
+    /// - cloning or dropping an `Arc`, `Weak` or `ProjectedArc`,
+    /// - [`Weak::upgrade`] and [`WeakRef::upgrade`],
+    /// - `ArcSwap::load` and `ArcSwap::store` on a cell holding the allocation,
+    /// - [`Arc::increment_strong_count`] and [`Arc::decrement_strong_count`],
+    /// - [`Arc::into_inner`], [`Arc::try_unwrap`], [`Arc::get_mut`] and the
+    ///   `downgrade` functions,
+    /// - [`Weak::strong_count`] and [`Weak::weak_count`].
+    pub unsafe fn clone_unsync(arc: &Self) -> Self {
+        let count = &arc.data().data_ref_count;
+        let n = count.load(Ordering::Relaxed);
+        if n > Self::MAX_REFCOUNT {
+            abort();
+        }
+        count.store(n + 1, Ordering::Relaxed);
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::Clone, arc.ptr);
+        Arc { ptr: arc.ptr }
+    }
+
+    /// Like dropping `arc`, but updates the count with a plain load and store
+    /// instead of an atomic read-modify-write operation, unless this is the
+    /// last `Arc`.
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Arc::clone_unsync`].
+    pub unsafe fn drop_unsync(arc: Self) {
+        let count = &arc.data().data_ref_count;
+        let n = count.load(Ordering::Relaxed);
+        if n == 1 {
+            // Dropping the data is rare, so just take the usual path.
+            drop(arc);
+        } else {
+            count.store(n - 1, Ordering::Relaxed);
+            #[cfg(feature = "trace")]
+            trace(ArcEvent::Drop, arc.ptr);
+            mem::forget(arc);
+        }
+    }
+}
+
+/// A borrowed weak pointer, created by [`Arc::as_weak_ref`].
+///
+/// The borrowed `Arc` keeps the data alive, so upgrading always succeeds.
+pub struct WeakRef<'a, T: ?Sized> {
+    arc: &'a Arc<T>,
+}
+
+impl<T: ?Sized> WeakRef<'_, T> {
+    /// Creates a new `Arc`, by only incrementing the strong count.
+    pub fn upgrade(&self) -> Arc<T> {
+        self.arc.clone()
+    }
+}
+
+impl<T: ?Sized> Clone for WeakRef<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for WeakRef<'_, T> {}
+
+/// A pointer to a part of the data of an `Arc`, which keeps the whole data
+/// alive. Created by [`Arc::project`].
+pub struct ProjectedArc<U: ?Sized> {
+    /// The original `Arc`, with the type of its data erased.
+    owner: Arc<dyn Owner>,
+    /// Points into the data of `owner`.
+    ptr: NonNull<U>,
+}
+
+/// Anything an `Arc` can hold, so that a `ProjectedArc` can drop it without
+/// knowing its type.
+trait Owner: Send + Sync {}
+
+impl<T: ?Sized + Send + Sync> Owner for T {}
+
+/// Acts like a `&U`, while the data it points into is shared between threads
+/// anyway.
+unsafe impl<U: ?Sized + Sync> Send for ProjectedArc<U> {}
+unsafe impl<U: ?Sized + Sync> Sync for ProjectedArc<U> {}
+
+impl<U: ?Sized> Deref for ProjectedArc<U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        // Safety: The owner keeps the data alive.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<U: ?Sized> Clone for ProjectedArc<U> {
+    fn clone(&self) -> Self {
+        ProjectedArc {
+            owner: self.owner.clone(),
+            ptr: self.ptr,
+        }
+    }
+}
+
+/// A change of the reference counts of an `Arc`, reported to the hook set with
+/// [`set_trace_hook`].
+#[cfg(feature = "trace")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArcEvent {
+    New,
+    Clone,
+    Drop,
+    Downgrade,
+    Upgrade,
+}
+
+/// The hook set with `set_trace_hook`, as a `fn(ArcEvent, usize)`, or null.
+#[cfg(feature = "trace")]
+static TRACE_HOOK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Sets a function to be called on every change of the reference counts of
+/// any `Arc`, with the address of its allocation (as in
+/// [`Arc::as_ptr_usize`]), or removes it. Useful for tracking down leaks.
+///
+/// Only available with the `trace` feature, so that normal builds don't pay
+/// for checking whether there is a hook.
+#[cfg(feature = "trace")]
+pub fn set_trace_hook(hook: Option<fn(ArcEvent, usize)>) {
+    let hook = hook.map_or(ptr::null_mut(), |hook| hook as *mut ());
+    TRACE_HOOK.store(hook, Ordering::Relaxed);
+}
+
+#[cfg(feature = "trace")]
+fn trace<T: ?Sized>(event: ArcEvent, ptr: NonNull<ArcData<T>>) {
+    let hook = TRACE_HOOK.load(Ordering::Relaxed);
+    if !hook.is_null() {
+        // Safety: Only `set_trace_hook` stores a non-null pointer, which is a
+        // `fn(ArcEvent, usize)`.
+        let hook = unsafe { mem::transmute::<*mut (), fn(ArcEvent, usize)>(hook) };
+        hook(event, ptr.as_ptr() as *const () as usize);
+    }
+}
+
+/// Returned by [`Arc::try_new`] when the allocation failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocError;
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("memory allocation failed")
+    }
+}
+
+impl Error for AllocError {}
+
+impl<T> Arc<MaybeUninit<T>> {
+    /// Converts to `Arc<T>`, once the data has been written.
+    ///
+    /// # Safety
+    ///
+    /// The data must have been fully initialized.
+    pub unsafe fn assume_init(arc: Self) -> Arc<T> {
+        let arc = ManuallyDrop::new(arc);
+        // `MaybeUninit<T>` has the same layout as `T`, so the `ArcData`s have
+        // the same layout as well.
+        Arc {
+            ptr: arc.ptr.cast(),
+        }
+    }
+}
+
+impl<T> Arc<[MaybeUninit<T>]> {
+    /// Converts to `Arc<[T]>`, once all elements have been written.
+    ///
+    /// This has its own name, so it doesn't collide with the `assume_init` for
+    /// a single value when called as `Arc::assume_init_slice(arc)`.
+    ///
+    /// # Safety
+    ///
+    /// All elements must have been fully initialized.
+    pub unsafe fn assume_init_slice(arc: Self) -> Arc<[T]> {
+        let arc = ManuallyDrop::new(arc);
+        // Same as for `Arc<MaybeUninit<T>>`, and the length stays the same.
+        Arc {
+            ptr: unsafe { NonNull::new_unchecked(arc.ptr.as_ptr() as *mut ArcData<[T]>) },
+        }
+    }
+}
+
+impl<T> Arc<[T]> {
+    /// Allocates an `Arc` for a slice of `len` zeroed elements.
+    ///
+    /// Calling [`Arc::assume_init_slice`] right away is only sound if all zeros
+    /// is a valid value of `T`, like for integers or atomics.
+    pub fn new_zeroed_slice(len: usize) -> Arc<[MaybeUninit<T>]> {
+        let ptr = Arc::<[MaybeUninit<T>]>::allocate_for_slice(len, alloc::alloc_zeroed);
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::New, ptr);
+        Arc { ptr }
+    }
+
+    /// Allocates an `Arc` for a slice of `len` elements, which are written one
+    /// by one afterwards, in any order, without `unsafe`.
+    ///
+    /// The returned builder keeps track of which elements have been written,
+    /// so [`ArcUninitSlice::into_init`] can check that all of them were.
+    pub fn new_uninit_slice(len: usize) -> ArcUninitSlice<T> {
+        ArcUninitSlice {
+            ptr: Self::allocate_for_slice(len, alloc::alloc),
+            initialized: vec![0; len.div_ceil(64)],
+            count: 0,
+        }
+    }
+
+    /// Allocates an `ArcData<[T]>` for `len` elements with `allocate`, with
+    /// both counters set to one, leaving the elements uninitialized.
+    fn allocate_for_slice(
+        len: usize,
+        allocate: unsafe fn(Layout) -> *mut u8,
+    ) -> NonNull<ArcData<[T]>> {
+        let layout = slice_layout::<T>(len);
+        // Safety: The layout is never zero-sized, because of the counters.
+        let mem = unsafe { allocate(layout) };
+        if mem.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        // The slice length becomes the metadata of the `ArcData` pointer.
+        let ptr = ptr::slice_from_raw_parts_mut(mem.cast::<T>(), len) as *mut ArcData<[T]>;
+        unsafe {
+            ptr::addr_of_mut!((*ptr).data_ref_count).write(AtomicUsize::new(1));
+            ptr::addr_of_mut!((*ptr).alloc_ref_count).write(AtomicUsize::new(1));
+            NonNull::new_unchecked(ptr)
+        }
+    }
+}
+
+/// After this many failures, `backoff` yields instead of spinning.
+const BACKOFF_SPIN_LIMIT: u32 = 6;
+
+/// Backs off after a failed compare-and-exchange, so threads fighting over the
+/// same counter don't keep invalidating each other's cache line. Spins twice
+/// as long after every failure, and starts yielding to other threads once that
+/// gets too long.
+fn backoff(failures: &mut u32) {
+    if *failures < BACKOFF_SPIN_LIMIT {
+        for _ in 0..1 << *failures {
+            hint::spin_loop();
+        }
+        *failures += 1;
+    } else {
+        #[cfg(feature = "std")]
+        crate::sync::thread::yield_now();
+        // Without `std`, there's no scheduler to yield to, so keep spinning.
+        #[cfg(not(feature = "std"))]
+        for _ in 0..1 << BACKOFF_SPIN_LIMIT {
+            hint::spin_loop();
+        }
+    }
+}
+
+/// Called when a reference count is about to overflow. Without `std`, there's
+/// no way to abort the process, so this panics instead.
+fn abort() -> ! {
+    #[cfg(feature = "std")]
+    std::process::abort();
+    #[cfg(not(feature = "std"))]
+    panic!("reference count overflow");
+}
+
+/// Returns the offset of the data within an `ArcData` for data with the given
+/// alignment.
+fn data_offset(align: usize) -> usize {
+    // Following `repr(C)`, the data comes right after the counters, padded to
+    // its alignment.
+    mem::offset_of!(ArcData<()>, data).next_multiple_of(align)
+}
+
+/// Returns the layout of an `ArcData<[T]>` with `len` elements, matching what
+/// `Layout::for_value` gives for it when it is freed.
+fn slice_layout<T>(len: usize) -> Layout {
+    Layout::new::<ArcData<()>>()
+        .extend(Layout::array::<T>(len).unwrap())
+        .unwrap()
+        .0
+        .pad_to_align()
+}
+
+/// Returns a pointer to the first element of the slice in an `ArcData<[T]>`.
+fn slice_elements<T>(ptr: NonNull<ArcData<[T]>>) -> *mut T {
+    unsafe { UnsafeCell::raw_get(ptr::addr_of!((*ptr.as_ptr()).data)).cast::<T>() }
+}
+
+/// Turns a shared array into a shared slice, without copying. With the
+/// `nightly` feature, this also happens implicitly, like for `Box`.
+impl<T, const N: usize> From<Arc<[T; N]>> for Arc<[T]> {
+    fn from(arc: Arc<[T; N]>) -> Self {
+        let arc = ManuallyDrop::new(arc);
+        // Unsizing the pointer adds the length, and keeps the counters as they
+        // are.
+        let ptr: NonNull<ArcData<[T]>> = arc.ptr;
+        Arc { ptr }
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<Arc<U>> for Arc<T> {}
+
+#[cfg(feature = "nightly")]
+impl<T: ?Sized + Unsize<U>, U: ?Sized> CoerceUnsized<Weak<U>> for Weak<T> {}
+
+impl<T> From<Vec<T>> for Arc<[T]> {
+    fn from(mut vec: Vec<T>) -> Self {
+        let len = vec.len();
+        let ptr = Self::allocate_for_slice(len, alloc::alloc);
+        unsafe {
+            // Move the elements over, and make sure the vector doesn't drop
+            // them too.
+            ptr::copy_nonoverlapping(vec.as_ptr(), slice_elements(ptr), len);
+            vec.set_len(0);
+        }
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::New, ptr);
+        Arc { ptr }
+    }
+}
+
+/// Moves the elements into a new allocation, without cloning them, and frees
+/// the box. The box can't be reused, since it has no room for the counters.
+impl<T> From<Box<[T]>> for Arc<[T]> {
+    fn from(boxed: Box<[T]>) -> Self {
+        // Turning the box into a vector keeps the allocation.
+        Self::from(boxed.into_vec())
+    }
+}
+
+impl<T: Clone> From<&[T]> for Arc<[T]> {
+    fn from(slice: &[T]) -> Self {
+        let ptr = Self::allocate_for_slice(slice.len(), alloc::alloc);
+        let mut guard = PartialSlice {
+            ptr,
+            initialized: 0,
+        };
+        for item in slice {
+            // Safety: The allocation has room for all elements of the slice.
+            unsafe {
+                slice_elements(ptr)
+                    .add(guard.initialized)
+                    .write(item.clone())
+            };
+            guard.initialized += 1;
+        }
+        mem::forget(guard);
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::New, ptr);
+        Arc { ptr }
+    }
+}
+
+impl<T> FromIterator<T> for Arc<[T]> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut iter = iter.into_iter();
+        let len = match iter.size_hint() {
+            (min, Some(max)) if min == max => min,
+            _ => return Arc::from(iter.collect::<Vec<T>>()),
+        };
+        // The length is known, so move the items straight into a single
+        // allocation. But size hints can be wrong, so don't rely on it.
+        let ptr = Self::allocate_for_slice(len, alloc::alloc);
+        let mut guard = PartialSlice {
+            ptr,
+            initialized: 0,
+        };
+        while guard.initialized < len {
+            let Some(item) = iter.next() else { break };
+            // Safety: The allocation has room for `len` elements.
+            unsafe { slice_elements(ptr).add(guard.initialized).write(item) };
+            guard.initialized += 1;
+        }
+        let extra = iter.next();
+        if guard.initialized == len && extra.is_none() {
+            mem::forget(guard);
+            #[cfg(feature = "trace")]
+            trace(ArcEvent::New, ptr);
+            return Arc { ptr };
+        }
+        // The hint was wrong. Move what we have into a vector instead, and
+        // only free the allocation.
+        let mut vec = Vec::with_capacity(guard.initialized + 1);
+        unsafe {
+            ptr::copy_nonoverlapping(slice_elements(ptr), vec.as_mut_ptr(), guard.initialized);
+            vec.set_len(guard.initialized);
+        }
+        guard.initialized = 0;
+        drop(guard);
+        vec.extend(extra);
+        vec.extend(iter);
+        Arc::from(vec)
+    }
+}
+
+/// Drops the initialized elements and frees the allocation of an `ArcData<[T]>`
+/// that is still being filled, if that is interrupted by a panic.
+struct PartialSlice<T> {
+    ptr: NonNull<ArcData<[T]>>,
+    initialized: usize,
+}
+
+impl<T> Drop for PartialSlice<T> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(
+                slice_elements(self.ptr),
+                self.initialized,
+            ));
+            let len = (self.ptr.as_ptr() as *mut [T]).len();
+            alloc::dealloc(self.ptr.as_ptr().cast(), slice_layout::<T>(len));
+        }
+    }
+}
+
+/// An `Arc<[T]>` that is still being filled, created by
+/// [`Arc::new_uninit_slice`].
+///
+/// Dropping it drops the elements written so far and frees the allocation.
+pub struct ArcUninitSlice<T> {
+    ptr: NonNull<ArcData<[T]>>,
+    /// One bit per element, set once it has been written.
+    initialized: Vec<u64>,
+    /// The number of bits set in `initialized`.
+    count: usize,
+}
+
+unsafe impl<T: Send> Send for ArcUninitSlice<T> {}
+unsafe impl<T: Sync> Sync for ArcUninitSlice<T> {}
+
+impl<T> ArcUninitSlice<T> {
+    pub fn len(&self) -> usize {
+        (self.ptr.as_ptr() as *mut [T]).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether the element at `index` has been written.
+    pub fn is_initialized(&self, index: usize) -> bool {
+        index < self.len() && self.initialized[index / 64] & 1 << (index % 64) != 0
+    }
+
+    /// Writes the element at `index`, dropping the one written there before,
+    /// if any, and returns a reference to it.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn write(&mut self, index: usize, value: T) -> &mut T {
+        assert!(index < self.len(), "index out of bounds");
+        // Safety: The index is in bounds, and nobody else can access the
+        // elements before the slice is turned into an `Arc`.
+        let element = unsafe { &mut *slice_elements(self.ptr).add(index) };
+        if self.is_initialized(index) {
+            *element = value;
+        } else {
+            // Safety: See above.
+            unsafe { ptr::write(element, value) };
+            self.initialized[index / 64] |= 1 << (index % 64);
+            self.count += 1;
+        }
+        element
+    }
+
+    /// Turns this into an `Arc<[T]>`, if all elements have been written, and
+    /// otherwise gives it back, so the missing elements can still be written.
+    pub fn into_init(self) -> Result<Arc<[T]>, Self> {
+        if self.count != self.len() {
+            return Err(self);
+        }
+        let mut this = ManuallyDrop::new(self);
+        // The elements are kept, but the bitmap isn't needed anymore.
+        drop(mem::take(&mut this.initialized));
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::New, this.ptr);
+        Ok(Arc { ptr: this.ptr })
+    }
+}
+
+impl<T> fmt::Debug for ArcUninitSlice<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArcUninitSlice")
+            .field("len", &self.len())
+            .field("initialized", &self.count)
+            .finish()
+    }
+}
+
+impl<T> Drop for ArcUninitSlice<T> {
+    fn drop(&mut self) {
+        let len = self.len();
+        for index in 0..len {
+            if self.is_initialized(index) {
+                // Safety: The element has been written, and is dropped only
+                // once, since the allocation is freed right after.
+                unsafe { ptr::drop_in_place(slice_elements(self.ptr).add(index)) };
+            }
         }
+        // Safety: Nothing else refers to the allocation.
+        unsafe { alloc::dealloc(self.ptr.as_ptr().cast(), slice_layout::<T>(len)) };
     }
 }
 
-impl<T> Deref for Arc<T> {
+impl<T: ?Sized> Deref for Arc<T> {
     type Target = T;
 
     fn deref(&self) -> &T {
@@ -152,17 +1061,84 @@ impl<T> Deref for Arc<T> {
     }
 }
 
-impl<T> Clone for Arc<T> {
+// Moving an `Arc` doesn't move the data it points to.
+impl<T: ?Sized> Unpin for Arc<T> {}
+
+impl<T: ?Sized> AsRef<T> for Arc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> Borrow<T> for Arc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+// `Borrow` requires comparisons and hashes to agree between `Arc<T>` and `T`,
+// so these all forward to the value. Only `eq` skips comparing a value with
+// itself, which makes a difference only if `T`'s `eq` isn't reflexive.
+
+/// Two `Arc`s to the same allocation are equal without comparing the value,
+/// which saves comparing a large value with itself. For a `T` whose `eq` isn't
+/// reflexive, like `f64` with NaN, use [`Arc::content_eq`] instead.
+impl<T: ?Sized + PartialEq> PartialEq for Arc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(self, other) || **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Arc<T> {}
+
+impl<T: ?Sized + Hash> Hash for Arc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+/// Compares and hashes an `Arc` by the identity of its allocation, rather than
+/// by value like `Arc` itself does.
+///
+/// Two `ByPtr`s are equal only if they point to the same allocation, even if
+/// the values are equal.
+pub struct ByPtr<T: ?Sized>(pub Arc<T>);
+
+impl<T: ?Sized> PartialEq for ByPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::as_ptr_usize(&self.0) == Arc::as_ptr_usize(&other.0)
+    }
+}
+
+impl<T: ?Sized> Eq for ByPtr<T> {}
+
+impl<T: ?Sized> Hash for ByPtr<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr_usize(&self.0).hash(state)
+    }
+}
+
+impl<T: ?Sized> Clone for ByPtr<T> {
+    fn clone(&self) -> Self {
+        ByPtr(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> Clone for Arc<T> {
     fn clone(&self) -> Self {
-        if self.data().data_ref_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
-            std::process::abort();
+        if self.data().data_ref_count.fetch_add(1, Ordering::Relaxed) > Self::MAX_REFCOUNT {
+            abort();
         }
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::Clone, self.ptr);
         Arc { ptr: self.ptr }
     }
 }
 
-impl<T> Drop for Arc<T> {
+impl<T: ?Sized> Drop for Arc<T> {
     fn drop(&mut self) {
+        #[cfg(feature = "trace")]
+        trace(ArcEvent::Drop, self.ptr);
         if self.data().data_ref_count.fetch_sub(1, Ordering::Release) == 1 {
             fence(Ordering::Acquire);
             // Safety: The data reference counter is zero,
@@ -177,10 +1153,20 @@ impl<T> Drop for Arc<T> {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "loom")))]
 mod tests {
-    use crate::arc::Arc;
-    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::arc::{Arc, ByPtr, Weak};
+    use std::collections::{HashMap, HashSet};
+    use std::future::Future;
+    use std::marker::PhantomPinned;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::pin::Pin;
+    use std::process::{Command, Stdio};
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::task::{Context, Poll, Waker};
+    use std::{env, mem};
 
     #[test]
     fn test() {
@@ -212,4 +1198,763 @@ mod tests {
         assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
         assert!(z.upgrade().is_none());
     }
+
+    #[test]
+    fn borrow() {
+        let key = Arc::new(String::from("key"));
+        let mut map = HashMap::new();
+        map.insert(key.clone(), 1);
+        assert_eq!(map.get(&String::from("key")), Some(&1));
+        assert_eq!(map.get(&key), Some(&1));
+        assert_eq!(key.as_ref(), "key");
+    }
+
+    #[test]
+    fn raw_strong_count() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop(i32);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let ptr = Arc::into_raw(Arc::new(DetectDrop(7)));
+        unsafe {
+            assert_eq!((*ptr).0, 7);
+            Arc::increment_strong_count(ptr);
+            Arc::decrement_strong_count(ptr);
+            assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+            Arc::increment_strong_count(ptr);
+            let arc = Arc::from_raw(ptr);
+            assert_eq!(arc.0, 7);
+            drop(arc);
+            assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+            Arc::decrement_strong_count(ptr);
+        }
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn slice() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop(i32);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let x: Arc<[DetectDrop]> = Arc::from(vec![DetectDrop(1), DetectDrop(2), DetectDrop(3)]);
+        assert_eq!(x.len(), 3);
+        assert_eq!(x[1].0, 2);
+        let y = x.clone();
+        let z = Arc::downgrade(&y);
+        drop(x);
+        assert_eq!(y.iter().map(|d| d.0).sum::<i32>(), 6);
+        drop(y);
+        // Each element was dropped exactly once.
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+        assert!(z.upgrade().is_none());
+    }
+
+    #[test]
+    fn slice_clone() {
+        let x: Arc<[String]> = Arc::from(&[String::from("a"), String::from("b")][..]);
+        assert_eq!(&*x, ["a", "b"]);
+        let raw = Arc::into_raw(x);
+        let x = unsafe { Arc::from_raw(raw) };
+        assert_eq!(&*x, ["a", "b"]);
+        let empty: Arc<[u64]> = Arc::from(Vec::new());
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn pin() {
+        struct NotUnpin(i32, PhantomPinned);
+        let x = Arc::pin(NotUnpin(5, PhantomPinned));
+        let y = x.clone();
+        let pinned: Pin<&NotUnpin> = y.as_ref();
+        assert_eq!(pinned.0, 5);
+        // Both point to the same, unmoved, data.
+        assert!(ptr::eq(&*x, &*y));
+    }
+
+    #[test]
+    fn get_pin_mut() {
+        let mut x = Arc::pin(async { 5 });
+        let mut cx = Context::from_waker(Waker::noop());
+        let y = x.clone();
+        assert!(Arc::get_pin_mut(&mut x).is_none());
+        drop(y);
+        let future = Arc::get_pin_mut(&mut x).unwrap();
+        assert_eq!(future.poll(&mut cx), Poll::Ready(5));
+    }
+
+    #[test]
+    fn into_inner() {
+        let x = Arc::new(String::from("hello"));
+        let y = x.clone();
+        assert_eq!(Arc::into_inner(x), None);
+        assert_eq!(Arc::into_inner(y).as_deref(), Some("hello"));
+
+        for _ in 0..100 {
+            let x = Arc::new(String::from("hello"));
+            let y = x.clone();
+            let results = std::thread::scope(|s| {
+                let a = s.spawn(move || Arc::into_inner(x));
+                let b = s.spawn(move || Arc::into_inner(y));
+                [a.join().unwrap(), b.join().unwrap()]
+            });
+            assert_eq!(results.iter().flatten().count(), 1);
+        }
+    }
+
+    #[test]
+    fn try_unwrap() {
+        let x = Arc::new(String::from("hello"));
+        let w = Arc::downgrade(&x);
+        let y = x.clone();
+        let x = Arc::try_unwrap(x).unwrap_err();
+        drop(y);
+        assert_eq!(Arc::try_unwrap(x).ok().as_deref(), Some("hello"));
+        assert!(w.upgrade().is_none());
+    }
+
+    #[test]
+    fn downgrade_n() {
+        let x = Arc::new(5);
+        assert!(Arc::downgrade_n(&x, 0).is_empty());
+        let weaks = Arc::downgrade_n(&x, 1000);
+        assert_eq!(weaks.len(), 1000);
+        assert_eq!(weaks[0].weak_count(), 1000);
+        assert!(weaks.iter().all(|w| *w.upgrade().unwrap() == 5));
+        drop(x);
+        assert!(weaks.iter().all(|w| w.upgrade().is_none()));
+        drop(weaks);
+    }
+
+    #[test]
+    fn project() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop {
+            name: String,
+        }
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let x = Arc::new(DetectDrop {
+            name: "hello".to_string(),
+        });
+        let weak = Arc::downgrade(&x);
+        let name = Arc::project(x, |x| x.name.as_str());
+        assert_eq!(&*name, "hello");
+        let name2 = name.clone();
+        drop(name);
+        assert_eq!(&*name2, "hello");
+        assert_eq!(weak.strong_count(), 1);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        std::thread::spawn(move || drop(name2)).join().unwrap();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn as_weak_ref() {
+        let x = Arc::new(5);
+        let alloc_ref_count = || x.data().alloc_ref_count.load(Ordering::Relaxed);
+        assert_eq!(alloc_ref_count(), 1);
+        let weak = Arc::as_weak_ref(&x);
+        let y = weak.upgrade();
+        assert_eq!(*y, 5);
+        assert_eq!(alloc_ref_count(), 1);
+        assert_eq!(x.data().data_ref_count.load(Ordering::Relaxed), 2);
+        drop(y);
+        assert_eq!(alloc_ref_count(), 1);
+        assert_eq!(x.data().data_ref_count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn ptr_eq() {
+        let a = Arc::new(1);
+        let b = Arc::new(1);
+        assert!(Arc::ptr_eq(&a, &a.clone()));
+        assert!(!Arc::ptr_eq(&a, &b));
+
+        let weak_a1 = Arc::downgrade(&a);
+        let weak_a2 = Arc::downgrade(&a);
+        let weak_b = Arc::downgrade(&b);
+        assert!(Weak::ptr_eq(&weak_a1, &weak_a2));
+        assert!(!Weak::ptr_eq(&weak_a1, &weak_b));
+        assert_eq!(weak_a1.as_ptr(), Arc::as_ptr(&a));
+        assert_ne!(weak_b.as_ptr(), Arc::as_ptr(&a));
+
+        let a_ptr = Arc::as_ptr(&a);
+        drop(a);
+        drop(b);
+        assert!(Weak::ptr_eq(&weak_a1, &weak_a2));
+        assert!(!Weak::ptr_eq(&weak_a1, &weak_b));
+        assert_eq!(weak_a2.as_ptr(), a_ptr);
+    }
+
+    #[test]
+    fn concurrent_upgrade() {
+        let x = Arc::new(5);
+        let weak = Arc::downgrade(&x);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        let y = weak.upgrade().unwrap();
+                        assert_eq!(*y, 5);
+                    }
+                    let ys: Vec<_> = (0..100).map(|_| weak.upgrade().unwrap()).collect();
+                    assert!(weak.strong_count() > ys.len());
+                });
+            }
+        });
+        assert_eq!(weak.strong_count(), 1);
+        assert_eq!(weak.weak_count(), 1);
+        drop(x);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn weak_counts() {
+        let x = Arc::new(1);
+        let y = x.clone();
+        let w = Arc::downgrade(&x);
+        let v = w.clone();
+        assert_eq!(w.strong_count(), 2);
+        assert_eq!(w.weak_count(), 2);
+        drop(v);
+        drop(x);
+        assert_eq!(w.strong_count(), 1);
+        assert_eq!(w.weak_count(), 1);
+        drop(y);
+        assert_eq!(w.strong_count(), 0);
+        assert_eq!(w.weak_count(), 1);
+    }
+
+    fn set_strong_count<T>(arc: &Arc<T>, n: usize) {
+        arc.data().data_ref_count.store(n, Ordering::Relaxed);
+    }
+
+    fn set_weak_count<T>(arc: &Arc<T>, n: usize) {
+        arc.data().alloc_ref_count.store(n, Ordering::Relaxed);
+    }
+
+    /// Going past `MAX_REFCOUNT` aborts the process, so the tests do that in a
+    /// child: this runs the test `name` again, with `ARC_OVERFLOW` set, and
+    /// checks that it aborted.
+    fn assert_aborts_in_child(name: &str) {
+        let status = Command::new(env::current_exe().unwrap())
+            .args(["--exact", name, "--test-threads=1"])
+            .env("ARC_OVERFLOW", "1")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success());
+        // A panic would exit with code 101, an abort is killed by a signal.
+        // Without `std`, there's only the panic.
+        #[cfg(feature = "std")]
+        assert_ne!(status.code(), Some(101));
+    }
+
+    #[test]
+    fn upgrade_overflow() {
+        let x = Arc::new(1);
+        let w = Arc::downgrade(&x);
+        set_strong_count(&x, Arc::<i32>::MAX_REFCOUNT);
+        let y = w.upgrade().unwrap();
+        assert_eq!(w.strong_count(), Arc::<i32>::MAX_REFCOUNT + 1);
+        set_strong_count(&x, 2);
+        drop(y);
+
+        if env::var_os("ARC_OVERFLOW").is_some() {
+            set_strong_count(&x, Arc::<i32>::MAX_REFCOUNT + 1);
+            mem::forget(w.upgrade());
+            unreachable!();
+        }
+        assert_aborts_in_child("arc::tests::upgrade_overflow");
+    }
+
+    #[test]
+    fn clone_overflow() {
+        let x = Arc::new(1);
+        set_strong_count(&x, Arc::<i32>::MAX_REFCOUNT);
+        let y = x.clone();
+        assert_eq!(
+            x.data().data_ref_count.load(Ordering::Relaxed),
+            Arc::<i32>::MAX_REFCOUNT + 1
+        );
+        set_strong_count(&x, 2);
+        drop(y);
+
+        if env::var_os("ARC_OVERFLOW").is_some() {
+            set_strong_count(&x, Arc::<i32>::MAX_REFCOUNT + 1);
+            mem::forget(x.clone());
+            unreachable!();
+        }
+        assert_aborts_in_child("arc::tests::clone_overflow");
+    }
+
+    #[test]
+    fn weak_clone_overflow() {
+        let x = Arc::new(1);
+        let w = Arc::downgrade(&x);
+        set_weak_count(&x, Arc::<i32>::MAX_REFCOUNT);
+        let v = w.clone();
+        assert_eq!(
+            x.data().alloc_ref_count.load(Ordering::Relaxed),
+            Arc::<i32>::MAX_REFCOUNT + 1
+        );
+        set_weak_count(&x, 3);
+        drop(v);
+
+        if env::var_os("ARC_OVERFLOW").is_some() {
+            set_weak_count(&x, Arc::<i32>::MAX_REFCOUNT + 1);
+            mem::forget(w.clone());
+            unreachable!();
+        }
+        assert_aborts_in_child("arc::tests::weak_clone_overflow");
+    }
+
+    #[test]
+    fn downgrade_overflow() {
+        let x = Arc::new(1);
+        set_weak_count(&x, Arc::<i32>::MAX_REFCOUNT);
+        let w = Arc::downgrade(&x);
+        assert_eq!(w.weak_count(), Arc::<i32>::MAX_REFCOUNT);
+        set_weak_count(&x, 2);
+        drop(w);
+
+        if env::var_os("ARC_OVERFLOW").is_some() {
+            set_weak_count(&x, Arc::<i32>::MAX_REFCOUNT + 1);
+            mem::forget(Arc::downgrade(&x));
+            unreachable!();
+        }
+        assert_aborts_in_child("arc::tests::downgrade_overflow");
+    }
+
+    #[test]
+    fn unsync() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let x = Arc::new(DetectDrop);
+        let w = Arc::downgrade(&x);
+        // Safety: None of these pointers leave this thread.
+        unsafe {
+            let y = Arc::clone_unsync(&x);
+            let z = Arc::clone_unsync(&y);
+            assert_eq!(w.strong_count(), 3);
+            Arc::drop_unsync(x);
+            drop(y);
+            assert_eq!(w.strong_count(), 1);
+            assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+            Arc::drop_unsync(z);
+        }
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+        assert!(w.upgrade().is_none());
+    }
+
+    #[test]
+    fn new_uninit_slice() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop(usize);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        // Write the elements back to front, across more than one bitmap word.
+        let mut slice = Arc::new_uninit_slice(100);
+        for i in (0..100).rev() {
+            slice.write(i, DetectDrop(i));
+        }
+        // Overwriting drops the element that was there.
+        assert_eq!(slice.write(50, DetectDrop(50)).0, 50);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+        let x: Arc<[DetectDrop]> = slice.into_init().unwrap();
+        assert!(x.iter().enumerate().all(|(i, d)| d.0 == i));
+        drop(x);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 101);
+    }
+
+    #[test]
+    fn new_uninit_slice_partial() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let mut slice = Arc::new_uninit_slice(3);
+        slice.write(0, DetectDrop);
+        slice.write(2, DetectDrop);
+        let Err(mut slice) = slice.into_init() else {
+            panic!("an element is missing");
+        };
+        assert!(slice.is_initialized(0));
+        assert!(!slice.is_initialized(1));
+        // The missing element can still be written.
+        slice.write(1, DetectDrop);
+        let slice = slice.into_init().unwrap();
+        assert_eq!(slice.len(), 3);
+        drop(slice);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+
+        // Dropping an unfinished slice only drops what was written.
+        let mut slice = Arc::new_uninit_slice(3);
+        slice.write(1, DetectDrop);
+        drop(slice);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 4);
+
+        let empty = Arc::<[DetectDrop]>::new_uninit_slice(0);
+        assert!(empty.into_init().unwrap().is_empty());
+    }
+
+    #[test]
+    fn new_uninit() {
+        let mut x = Arc::<[u64; 1024]>::new_uninit();
+        Arc::get_mut(&mut x).unwrap().write([7; 1024]);
+        let x = unsafe { Arc::assume_init(x) };
+        assert!(x.iter().all(|&n| n == 7));
+        let y = x.clone();
+        drop(x);
+        assert_eq!(y[1023], 7);
+    }
+
+    #[test]
+    fn from_iter() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop(i32);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let x: Arc<[DetectDrop]> = (0..100).map(DetectDrop).collect();
+        assert_eq!(x.len(), 100);
+        assert_eq!(x[99].0, 99);
+        let y: Arc<[DetectDrop]> = (0..100).filter(|n| n % 2 == 0).map(DetectDrop).collect();
+        assert_eq!(y.len(), 50);
+        assert_eq!(y[49].0, 98);
+        drop((x, y));
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 150);
+
+        // An iterator with a wrong, exact-looking size hint.
+        struct Lying(std::ops::Range<i32>, usize);
+        impl Iterator for Lying {
+            type Item = i32;
+            fn next(&mut self) -> Option<i32> {
+                self.0.next()
+            }
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.1, Some(self.1))
+            }
+        }
+        let short: Arc<[i32]> = Lying(0..3, 5).collect();
+        assert_eq!(&*short, [0, 1, 2]);
+        let long: Arc<[i32]> = Lying(0..5, 3).collect();
+        assert_eq!(&*long, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn get_mut_unchecked() {
+        let mut x = Arc::new(vec![1]);
+        // Safety: `x` was just created, so it's the only pointer to the data.
+        unsafe { Arc::get_mut_unchecked(&mut x) }.push(2);
+        assert_eq!(*x, [1, 2]);
+    }
+
+    #[test]
+    fn eq_short_circuit() {
+        struct PanicOnEq(i32);
+        impl PartialEq for PanicOnEq {
+            fn eq(&self, other: &Self) -> bool {
+                assert_ne!(self.0, 0, "compared the values");
+                self.0 == other.0
+            }
+        }
+        let a = Arc::new(PanicOnEq(0));
+        // The same allocation is equal without comparing the values.
+        assert!(a == a.clone());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| Arc::content_eq(&a, &a.clone())));
+        assert!(result.is_err());
+
+        // Different allocations are compared by value.
+        assert!(Arc::new(PanicOnEq(1)) == Arc::new(PanicOnEq(1)));
+        assert!(Arc::new(PanicOnEq(1)) != Arc::new(PanicOnEq(2)));
+
+        let nan = Arc::new(f64::NAN);
+        assert!(nan == nan.clone());
+        assert!(!Arc::content_eq(&nan, &nan.clone()));
+    }
+
+    #[test]
+    fn by_ptr() {
+        let a = Arc::new(String::from("same"));
+        let b = Arc::new(String::from("same"));
+        assert!(a == b);
+        assert_ne!(Arc::as_ptr_usize(&a), Arc::as_ptr_usize(&b));
+        let mut set = HashSet::new();
+        assert!(set.insert(ByPtr(a.clone())));
+        assert!(set.insert(ByPtr(b)));
+        assert!(!set.insert(ByPtr(a)));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn new_zeroed() {
+        let x = unsafe { Arc::assume_init(Arc::<[u64; 16]>::new_zeroed()) };
+        assert_eq!(*x, [0; 16]);
+        let x: Arc<[u8]> = unsafe { Arc::assume_init_slice(Arc::<[u8]>::new_zeroed_slice(1024)) };
+        assert_eq!(x.len(), 1024);
+        assert!(x.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn downgrade_with_strong_count() {
+        let x = Arc::new(1);
+        let (w, count) = Arc::downgrade_with_strong_count(&x);
+        assert_eq!(count, 1);
+        let clones = [x.clone(), x.clone()];
+        let (w2, count) = Arc::downgrade_with_strong_count(&clones[0]);
+        assert_eq!(count, 3);
+        drop(clones);
+        let (_, count) = Arc::downgrade_with_strong_count(&x);
+        assert_eq!(count, 1);
+        drop(x);
+        assert!(w.upgrade().is_none());
+        assert!(w2.upgrade().is_none());
+    }
+
+    #[test]
+    fn try_downgrade() {
+        let x = Arc::new(1);
+        // Pretend another thread is in the middle of `get_mut`, which
+        // temporarily locks the weak count like this.
+        x.data()
+            .alloc_ref_count
+            .store(usize::MAX, Ordering::Relaxed);
+        assert!(Arc::try_downgrade(&x).is_none());
+        x.data().alloc_ref_count.store(1, Ordering::Release);
+        let w = Arc::try_downgrade(&x).unwrap();
+        assert_eq!(*w.upgrade().unwrap(), 1);
+    }
+
+    #[test]
+    fn get_mut_with_weak() {
+        let mut x = Arc::new(1);
+        *Arc::get_mut(&mut x).unwrap() += 1;
+        let w = Arc::downgrade(&x);
+        // The weak pointer is never upgraded, but still blocks mutation.
+        assert!(Arc::get_mut(&mut x).is_none());
+        assert_eq!(w.strong_count(), 1);
+        assert_eq!(w.weak_count(), 1);
+        drop(w);
+        *Arc::get_mut(&mut x).unwrap() += 1;
+        assert_eq!(*x, 3);
+    }
+
+    #[test]
+    fn get_mut_with_concurrent_upgrade() {
+        // Hand a `Weak` to another thread, which upgrades it and drops it
+        // while keeping the upgraded `Arc` for a moment, while we keep trying
+        // `get_mut`. That must fail until the upgraded `Arc` is gone too.
+        let mut x = Arc::new(0);
+        let holding = AtomicBool::new(false);
+        let (sender, receiver) = mpsc::channel::<Weak<i32>>();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for weak in receiver {
+                    let upgraded = weak.upgrade().unwrap();
+                    holding.store(true, Ordering::Relaxed);
+                    drop(weak);
+                    for _ in 0..10 {
+                        std::hint::spin_loop();
+                    }
+                    let value = *upgraded;
+                    std::thread::yield_now();
+                    assert_eq!(*upgraded, value);
+                    holding.store(false, Ordering::Relaxed);
+                }
+            });
+            for _ in 0..10_000 {
+                sender.send(Arc::downgrade(&x)).unwrap();
+                loop {
+                    if let Some(value) = Arc::get_mut(&mut x) {
+                        // `get_mut` synchronizes with dropping the upgraded
+                        // `Arc`, which comes after resetting the flag.
+                        assert!(!holding.load(Ordering::Relaxed));
+                        *value += 1;
+                        break;
+                    }
+                    std::thread::yield_now();
+                }
+            }
+            drop(sender);
+        });
+        assert_eq!(*x, 10_000);
+    }
+
+    #[test]
+    fn from_array() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop(i32);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let array = Arc::new([1, 2, 3, 4].map(DetectDrop));
+        let other = array.clone();
+        let slice: Arc<[DetectDrop]> = Arc::from(array);
+        assert_eq!(slice.len(), 4);
+        assert_eq!(slice[2].0, 3);
+        assert_eq!(slice.iter().map(|d| d.0).sum::<i32>(), 10);
+        drop(other);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(slice);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 4);
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn trace_hook() {
+        use crate::arc::{set_trace_hook, ArcEvent};
+        use std::sync::Mutex;
+        static EVENTS: Mutex<Vec<(ArcEvent, usize)>> = Mutex::new(Vec::new());
+        set_trace_hook(Some(|event, ptr| EVENTS.lock().unwrap().push((event, ptr))));
+        let x = Arc::new(1);
+        let addr = Arc::as_ptr_usize(&x);
+        let y = x.clone();
+        drop(y);
+        let w = Arc::downgrade(&x);
+        let z = w.upgrade().unwrap();
+        drop(z);
+        drop(x);
+        set_trace_hook(None);
+        // Other tests create `Arc`s concurrently, and might even have reused
+        // the same allocation before.
+        let events: Vec<ArcEvent> = EVENTS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|&&(_, ptr)| ptr == addr)
+            .map(|&(event, _)| event)
+            .collect();
+        let start = events.iter().rposition(|&e| e == ArcEvent::New).unwrap();
+        use ArcEvent::*;
+        assert_eq!(
+            events[start..],
+            [New, Clone, Drop, Downgrade, Upgrade, Drop, Drop]
+        );
+        drop(w);
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn coerce_unsized() {
+        let slice: Arc<[i32]> = Arc::new([1, 2, 3, 4]);
+        assert_eq!(&*slice, [1, 2, 3, 4]);
+        let array = Arc::new([5]);
+        let weak = Arc::downgrade(&array);
+        let weak: crate::arc::Weak<[i32]> = weak;
+        drop(array);
+        assert!(weak.upgrade().is_none());
+    }
+}
+
+/// Model tests, which loom runs under every possible interleaving of the
+/// threads, with the `loom` feature.
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use crate::arc::Arc;
+    use loom::alloc::Track;
+    use loom::cell::UnsafeCell;
+    use loom::thread;
+
+    /// Written when dropped, so loom catches a drop that races with a read.
+    struct Data(Track<UnsafeCell<i32>>);
+
+    // Safety: Only the drop writes, after all other references are gone.
+    unsafe impl Sync for Data {}
+
+    impl Data {
+        fn new() -> Self {
+            Self(Track::new(UnsafeCell::new(1)))
+        }
+
+        fn read(&self) -> i32 {
+            self.0.get_ref().with(|value| unsafe { *value })
+        }
+    }
+
+    impl Drop for Data {
+        fn drop(&mut self) {
+            self.0.get_ref().with_mut(|value| unsafe { *value = 0 });
+        }
+    }
+
+    #[test]
+    fn clone_and_drop() {
+        loom::model(|| {
+            let a = Arc::new(Data::new());
+            let b = a.clone();
+            let c = a.clone();
+            let t1 = thread::spawn(move || assert_eq!(b.read(), 1));
+            let t2 = thread::spawn(move || assert_eq!(c.read(), 1));
+            assert_eq!(a.read(), 1);
+            drop(a);
+            t1.join().unwrap();
+            t2.join().unwrap();
+            // Loom reports the `Track` as leaked if the data was never dropped.
+        });
+    }
+
+    #[test]
+    fn get_mut_with_concurrent_upgrade() {
+        loom::model(|| {
+            let mut a = Arc::new(Data::new());
+            let weak = Arc::downgrade(&a);
+            let t = thread::spawn(move || {
+                let b = weak.upgrade().unwrap();
+                drop(weak);
+                assert_eq!(b.read(), 1);
+            });
+            // Whenever this succeeds, the other thread must be done with the
+            // data, or loom catches the write racing with its read.
+            if let Some(data) = Arc::get_mut(&mut a) {
+                data.0.get_ref().with_mut(|value| unsafe { *value = 2 });
+            }
+            t.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn downgrade_and_upgrade() {
+        loom::model(|| {
+            let a = Arc::new(Data::new());
+            let weak = Arc::downgrade(&a);
+            let t = thread::spawn(move || {
+                if let Some(a) = weak.upgrade() {
+                    assert_eq!(a.read(), 1);
+                }
+                weak
+            });
+            drop(a);
+            let weak = t.join().unwrap();
+            assert!(weak.upgrade().is_none());
+        });
+    }
 }
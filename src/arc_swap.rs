@@ -0,0 +1,164 @@
+//! An `Arc` that can be replaced atomically while other threads are loading
+//! it.
+//!
+//! The cell owns one strong reference to the current value. A reader can't
+//! simply load the pointer and increment the count, since a concurrent `store`
+//! might drop the last reference in between. Instead, readers protect the
+//! pointer with a hazard pointer, and `store` retires the old reference
+//! through the cell's [`HazardDomain`], which only drops it once no reader is
+//! in the middle of loading it.
+
+use crate::arc::Arc;
+use crate::hazard::HazardDomain;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+pub struct ArcSwap<T> {
+    /// Points to the data of an `Arc`, as returned by `Arc::into_raw`.
+    ptr: AtomicPtr<T>,
+    domain: HazardDomain,
+    /// The cell shares and drops `Arc<T>`s across threads.
+    _marker: PhantomData<Arc<T>>,
+}
+
+/// Gives back the strong reference that the cell held.
+unsafe fn release<T>(ptr: *mut T) {
+    drop(unsafe { Arc::from_raw(ptr) });
+}
+
+impl<T> ArcSwap<T> {
+    pub fn new(value: Arc<T>) -> Self {
+        Self {
+            ptr: AtomicPtr::new(Arc::into_raw(value) as *mut T),
+            domain: HazardDomain::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a new reference to the current value.
+    ///
+    /// This doesn't take a lock, but allocates a hazard slot if all existing
+    /// ones are in use.
+    pub fn load(&self) -> Arc<T> {
+        let hazard = self.domain.acquire();
+        let ptr = hazard.protect(&self.ptr);
+        // Safety: While protected, the cell's reference to `ptr` can't be
+        // released, so the count is still at least one.
+        unsafe {
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        }
+    }
+
+    /// Replaces the value, dropping the cell's reference to the old one.
+    pub fn store(&self, value: Arc<T>) {
+        let new = Arc::into_raw(value) as *mut T;
+        // AcqRel: Release to publish the new value to readers, Acquire so
+        // dropping the old value happens after its initialization.
+        let old = self.ptr.swap(new, Ordering::AcqRel);
+        // Safety: `old` was unlinked by the swap, and only this thread got it.
+        // Dropping an `Arc<T>` from any thread is fine, since `ArcSwap<T>` is
+        // only `Send` or `Sync` when `Arc<T>` is.
+        unsafe { self.domain.retire(old, release) };
+    }
+
+    /// Replaces the value with `new`, but only if the cell still holds the
+    /// same allocation as `current`. Otherwise, `new` is given back.
+    ///
+    /// Since `current` keeps its allocation alive, the allocation can't have
+    /// been freed and reused for another value in the meantime.
+    pub fn compare_and_swap(&self, current: &Arc<T>, new: Arc<T>) -> Result<(), Arc<T>> {
+        let current = Arc::as_ptr(current) as *mut T;
+        let new = Arc::into_raw(new) as *mut T;
+        // Same orderings as in `store`.
+        match self
+            .ptr
+            .compare_exchange(current, new, Ordering::AcqRel, Ordering::Relaxed)
+        {
+            // Safety: Same as in `store`.
+            Ok(old) => unsafe {
+                self.domain.retire(old, release);
+                Ok(())
+            },
+            // Safety: `new` was never stored, so we still own its reference.
+            Err(_) => Err(unsafe { Arc::from_raw(new) }),
+        }
+    }
+}
+
+impl<T> Drop for ArcSwap<T> {
+    fn drop(&mut self) {
+        // Safety: The cell still holds its reference to the current value.
+        unsafe { release(*self.ptr.get_mut()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arc::Arc;
+    use crate::arc_swap::ArcSwap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    const ALIVE: usize = 0xa11fe;
+
+    #[test]
+    fn test() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Config(usize);
+        impl Drop for Config {
+            fn drop(&mut self) {
+                assert_eq!(self.0, ALIVE);
+                self.0 = 0;
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let cell = ArcSwap::new(Arc::new(Config(ALIVE)));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        assert_eq!(cell.load().0, ALIVE);
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..1_000 {
+                    cell.store(Arc::new(Config(ALIVE)));
+                }
+            });
+        });
+        let last = cell.load();
+        drop(cell);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1_000);
+        drop(last);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1_001);
+    }
+
+    #[test]
+    fn compare_and_swap() {
+        let cell = ArcSwap::new(Arc::new(0));
+        let current = cell.load();
+        let results = thread::scope(|s| {
+            let a = s.spawn(|| cell.compare_and_swap(&current, Arc::new(1)));
+            let b = s.spawn(|| cell.compare_and_swap(&current, Arc::new(2)));
+            [a.join().unwrap(), b.join().unwrap()]
+        });
+        let winner = *cell.load();
+        match results {
+            [Ok(()), Err(lost)] | [Err(lost), Ok(())] => {
+                assert_ne!(*lost, winner);
+                // The loser got back the only reference to its value.
+                assert_eq!(Arc::downgrade(&lost).strong_count(), 1);
+            }
+            _ => panic!("exactly one compare_and_swap must succeed"),
+        }
+        assert_ne!(winner, 0);
+
+        // An update based on an outdated value fails.
+        assert!(cell.compare_and_swap(&current, Arc::new(3)).is_err());
+        let latest = cell.load();
+        assert!(cell.compare_and_swap(&latest, Arc::new(3)).is_ok());
+        assert_eq!(*cell.load(), 3);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::spin_lock::SpinLock;
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem::{self, ManuallyDrop};
+use std::ptr::NonNull;
+#[cfg(target_has_atomic = "64")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU8, Ordering};
+
+/// A cell whose value can be loaded, stored and swapped from multiple threads.
+///
+/// If `T` implements [`NoUninit`], and has the same size as one of the atomic
+/// integer types, and the cell was created with
+/// [`new_lock_free`](Self::new_lock_free), the value is accessed through that
+/// atomic type, which makes all operations lock-free. Otherwise, the value is
+/// protected by a spin lock. Use [`is_lock_free`](Self::is_lock_free) to check
+/// which one it is.
+///
+/// Without specialization, [`new`](Self::new) can't tell whether `T` has
+/// padding, so it always uses the lock, even for a `u32`. Use `new_lock_free`
+/// for the lock-free path. It only accepts types without padding:
+///
+/// ```compile_fail
+/// use rust_atomics::atomic_cell::AtomicCell;
+/// // Has a padding byte between the `u8` and the `u16`.
+/// let cell = AtomicCell::new_lock_free((1u8, 2u16));
+/// ```
+// The alignment of the largest atomic type, so that `value` is always suitably
+// aligned for whichever one we use.
+#[repr(C, align(8))]
+pub struct AtomicCell<T> {
+    value: UnsafeCell<T>,
+    /// Only set if `T` implements `NoUninit` and fits in an atomic integer.
+    lock_free: bool,
+    /// Only used if the cell isn't lock-free.
+    lock: SpinLock<()>,
+}
+
+unsafe impl<T> Sync for AtomicCell<T> where T: Send {}
+
+/// Types whose values can be copied into an integer of the same size: every
+/// byte of them is always initialized, unlike padding bytes.
+///
+/// # Safety
+///
+/// `T` must not have padding bytes, or any other bytes that may be
+/// uninitialized, like a `MaybeUninit` or a union.
+pub unsafe trait NoUninit {}
+
+macro_rules! no_uninit {
+    ($($t:ty),*) => {
+        $(unsafe impl NoUninit for $t {})*
+    };
+}
+
+// Safety: Primitives have no padding, and every byte of them is initialized.
+no_uninit!(u8, u16, u32, u64, usize);
+no_uninit!(i8, i16, i32, i64, isize);
+no_uninit!(f32, f64, bool, char, ());
+
+// Safety: Pointers to sized types are plain addresses. The `Option`s use the
+// null pointer for `None`.
+unsafe impl<T> NoUninit for *const T {}
+unsafe impl<T> NoUninit for *mut T {}
+unsafe impl<T> NoUninit for NonNull<T> {}
+unsafe impl<T> NoUninit for Option<NonNull<T>> {}
+unsafe impl<T> NoUninit for &T {}
+unsafe impl<T> NoUninit for Box<T> {}
+unsafe impl<T> NoUninit for Option<Box<T>> {}
+
+// Safety: Array elements are laid out without any gaps.
+unsafe impl<T: NoUninit, const N: usize> NoUninit for [T; N] {}
+
+/// Runs `$atomic` with `$a` bound to the value viewed as the atomic integer of
+/// the same size, or `$locked` with the lock held if the cell isn't lock-free.
+macro_rules! dispatch {
+    ($cell:ident, $a:ident => $atomic:expr, _ => $locked:expr) => {
+        // Safety: `lock_free` is only set for a `T` without uninitialized
+        // bytes, and of the same size as the atomic. The cell is aligned for
+        // it. All accesses to the value go through the same atomic type.
+        match mem::size_of::<T>() {
+            1 if $cell.lock_free => {
+                let $a = unsafe { &*($cell.value.get() as *const AtomicU8) };
+                $atomic
+            }
+            2 if $cell.lock_free => {
+                let $a = unsafe { &*($cell.value.get() as *const AtomicU16) };
+                $atomic
+            }
+            4 if $cell.lock_free => {
+                let $a = unsafe { &*($cell.value.get() as *const AtomicU32) };
+                $atomic
+            }
+            #[cfg(target_has_atomic = "64")]
+            8 if $cell.lock_free => {
+                let $a = unsafe { &*($cell.value.get() as *const AtomicU64) };
+                $atomic
+            }
+            _ => {
+                let _guard = $cell.lock.lock();
+                $locked
+            }
+        }
+    };
+}
+
+impl<T> AtomicCell<T> {
+    /// Creates a cell that protects the value with a lock, for any `T`. This
+    /// is never lock-free, not even if `T` fits in an atomic integer; use
+    /// [`new_lock_free`](Self::new_lock_free) for that.
+    pub const fn new(value: T) -> Self {
+        Self {
+            value: UnsafeCell::new(value),
+            lock_free: false,
+            lock: SpinLock::new(()),
+        }
+    }
+
+    /// Creates a cell that is lock-free if `T` fits in an atomic integer, and
+    /// uses a lock like [`new`](Self::new) otherwise.
+    pub const fn new_lock_free(value: T) -> Self
+    where
+        T: NoUninit,
+    {
+        let size = mem::size_of::<T>();
+        Self {
+            value: UnsafeCell::new(value),
+            lock_free: matches!(size, 1 | 2 | 4) || (size == 8 && cfg!(target_has_atomic = "64")),
+            lock: SpinLock::new(()),
+        }
+    }
+
+    /// Returns whether operations on this cell are lock-free.
+    pub fn is_lock_free(&self) -> bool {
+        self.lock_free
+    }
+
+    /// Stores `value` in the cell, and returns the previous value.
+    pub fn swap(&self, value: T) -> T {
+        let value = ManuallyDrop::new(value);
+        dispatch!(self, a => unsafe {
+            // Safety: The integer holds the bytes of a `T`, and ownership of
+            // `value` moves into the cell.
+            mem::transmute_copy(&a.swap(mem::transmute_copy(&*value), Ordering::AcqRel))
+        }, _ => unsafe {
+            // Safety: We hold the lock.
+            mem::replace(&mut *self.value.get(), ManuallyDrop::into_inner(value))
+        })
+    }
+
+    /// Stores `value` in the cell, dropping the previous value.
+    pub fn store(&self, value: T) {
+        drop(self.swap(value));
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: Copy> AtomicCell<T> {
+    /// Returns a copy of the value in the cell.
+    pub fn load(&self) -> T {
+        dispatch!(self, a => unsafe {
+            // Safety: The integer holds the bytes of a `T`, which is `Copy`.
+            mem::transmute_copy(&a.load(Ordering::Acquire))
+        }, _ => unsafe {
+            // Safety: We hold the lock.
+            *self.value.get()
+        })
+    }
+}
+
+impl<T: Default> Default for AtomicCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for AtomicCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AtomicCell")
+            .field("value", &self.load())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atomic_cell::{AtomicCell, NoUninit};
+    use std::thread;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Rgba(u8, u8, u8, u8);
+
+    // Safety: Four bytes, without padding between them.
+    unsafe impl NoUninit for Rgba {}
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Large([u64; 4]);
+
+    #[test]
+    fn lock_free() {
+        let cell = AtomicCell::new_lock_free(Rgba(1, 2, 3, 4));
+        assert!(cell.is_lock_free());
+        assert_eq!(cell.load(), Rgba(1, 2, 3, 4));
+        assert_eq!(cell.swap(Rgba(5, 6, 7, 8)), Rgba(1, 2, 3, 4));
+        cell.store(Rgba(9, 9, 9, 9));
+        assert_eq!(cell.into_inner(), Rgba(9, 9, 9, 9));
+    }
+
+    #[test]
+    fn locked() {
+        let cell = AtomicCell::new(Large([1; 4]));
+        assert!(!cell.is_lock_free());
+        assert_eq!(cell.load(), Large([1; 4]));
+        assert_eq!(cell.swap(Large([2; 4])), Large([1; 4]));
+        cell.store(Large([3; 4]));
+        assert_eq!(cell.into_inner(), Large([3; 4]));
+    }
+
+    #[test]
+    fn owned_values() {
+        // A `Box` fits in an atomic integer, but must not be dropped twice or
+        // leaked.
+        let cell = AtomicCell::new_lock_free(Box::new(1));
+        assert!(cell.is_lock_free());
+        assert_eq!(*cell.swap(Box::new(2)), 1);
+        cell.store(Box::new(3));
+        assert_eq!(*cell.into_inner(), 3);
+    }
+
+    /// Every thread keeps swapping its own value in, and whatever it got out
+    /// back in again. If no swap is lost or duplicated, all values are still
+    /// around exactly once at the end.
+    fn swap_stress<T: Copy + Send + PartialEq>(cell: AtomicCell<T>, values: Vec<T>) {
+        let mut held: Vec<T> = thread::scope(|s| {
+            let threads: Vec<_> = values[1..]
+                .iter()
+                .map(|&value| {
+                    let cell = &cell;
+                    s.spawn(move || {
+                        let mut value = value;
+                        for _ in 0..10_000 {
+                            value = cell.swap(value);
+                        }
+                        value
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        held.push(cell.into_inner());
+        assert_eq!(held.len(), values.len());
+        for value in values {
+            assert_eq!(held.iter().filter(|&&v| v == value).count(), 1);
+        }
+    }
+
+    #[test]
+    fn swap_stress_lock_free() {
+        let values: Vec<_> = (0..8).map(|i| Rgba(i, i, i, i)).collect();
+        swap_stress(AtomicCell::new_lock_free(values[0]), values);
+    }
+
+    #[test]
+    fn swap_stress_locked() {
+        let values: Vec<_> = (0..8).map(|i| Large([i; 4])).collect();
+        swap_stress(AtomicCell::new(values[0]), values);
+    }
+}
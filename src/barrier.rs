@@ -0,0 +1,93 @@
+use crate::futex::{wait, wake_all};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Lets a fixed number of threads wait for each other, round after round.
+pub struct Barrier {
+    n: usize,
+    arrived: AtomicUsize,
+    /// Incremented every time a round completes.
+    generation: AtomicU32,
+}
+
+impl Barrier {
+    pub const fn new(n: usize) -> Self {
+        Self {
+            n,
+            arrived: AtomicUsize::new(0),
+            generation: AtomicU32::new(0),
+        }
+    }
+
+    /// Blocks until `n` threads are waiting, then releases all of them.
+    ///
+    /// Exactly one thread of each round, the last one to arrive, is the
+    /// leader.
+    pub fn wait(&self) -> BarrierWaitResult {
+        // The round can't complete before we arrived ourselves, so this is
+        // the generation of the round we are joining.
+        let generation = self.generation.load(Ordering::Acquire);
+        // AcqRel, so the leader sees everything the others did before
+        // arriving.
+        if self.arrived.fetch_add(1, Ordering::AcqRel) + 1 < self.n {
+            // Comparing with the generation we joined (rather than waiting
+            // for the arrival count to change) means that threads already
+            // racing into the next round can't confuse us. The counter would
+            // have to wrap around completely to come back to our generation,
+            // but it can't move past the next round without us.
+            while self.generation.load(Ordering::Acquire) == generation {
+                wait(&self.generation, generation);
+            }
+            BarrierWaitResult { is_leader: false }
+        } else {
+            // Reset the count before starting the next round, so threads that
+            // see the new generation also see the reset.
+            self.arrived.store(0, Ordering::Relaxed);
+            self.generation.fetch_add(1, Ordering::Release);
+            wake_all(&self.generation);
+            BarrierWaitResult { is_leader: true }
+        }
+    }
+}
+
+pub struct BarrierWaitResult {
+    is_leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Whether this thread was the one that released the others.
+    pub fn is_leader(&self) -> bool {
+        self.is_leader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::barrier::Barrier;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test() {
+        const THREADS: usize = 4;
+        const ROUNDS: usize = 100;
+        let barrier = Barrier::new(THREADS);
+        let counts: [AtomicUsize; ROUNDS] = [const { AtomicUsize::new(0) }; ROUNDS];
+        let leaders = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    for count in &counts {
+                        count.fetch_add(1, Ordering::Relaxed);
+                        if barrier.wait().is_leader() {
+                            leaders.fetch_add(1, Ordering::Relaxed);
+                        }
+                        // Nobody gets past the barrier before everyone
+                        // arrived.
+                        assert_eq!(count.load(Ordering::Relaxed), THREADS);
+                    }
+                });
+            }
+        });
+        assert_eq!(leaders.load(Ordering::Relaxed), ROUNDS);
+    }
+}
@@ -0,0 +1,202 @@
+//! A one-shot channel that delivers a copy of its single message to each of a
+//! fixed number of receivers.
+
+use crate::mutex::Mutex;
+use crate::one_shot::RecvError;
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, Thread};
+
+pub struct Sender<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+pub struct Receiver<'a, T> {
+    channel: &'a Channel<T>,
+    /// Our slot in `Channel::receiving_threads`.
+    index: usize,
+}
+
+pub struct Channel<T> {
+    message: UnsafeCell<MaybeUninit<T>>,
+    ready: AtomicBool,
+    /// Set when the sender is dropped, whether it sent a message or not.
+    sender_dropped: AtomicBool,
+    /// The number of receivers that haven't been dropped yet. The last one
+    /// drops the message.
+    remaining: AtomicUsize,
+    /// For every receiver, the thread blocked in `receive`, if any.
+    receiving_threads: Vec<Mutex<Option<Thread>>>,
+}
+
+/// Receivers on different threads all get a `&T` to clone from.
+unsafe impl<T> Sync for Channel<T> where T: Send + Sync {}
+
+impl<T> Sender<'_, T> {
+    pub fn send(self, message: T) {
+        unsafe { (*self.channel.message.get()).write(message) };
+        self.channel.ready.store(true, Ordering::Release);
+        // Dropping `self` wakes up the receivers.
+    }
+}
+
+impl<T> Drop for Sender<'_, T> {
+    fn drop(&mut self) {
+        self.channel.sender_dropped.store(true, Ordering::Release);
+        for slot in &self.channel.receiving_threads {
+            if let Some(thread) = &*slot.lock() {
+                thread.unpark();
+            }
+        }
+    }
+}
+
+impl<T: Clone> Receiver<'_, T> {
+    pub fn is_ready(&self) -> bool {
+        self.channel.ready.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until the message arrives and returns a copy of it, or returns
+    /// an error if the sender is dropped without sending one.
+    pub fn receive(self) -> Result<T, RecvError> {
+        // Same as in `one_shot`: if the sender looks for our handle before we
+        // stored it, the lock makes its flags visible to the checks below.
+        *self.channel.receiving_threads[self.index].lock() = Some(thread::current());
+        while !self.channel.ready.load(Ordering::Acquire) {
+            if self.channel.sender_dropped.load(Ordering::Acquire) {
+                if !self.channel.ready.load(Ordering::Acquire) {
+                    return Err(RecvError);
+                }
+                break;
+            }
+            thread::park();
+        }
+        // Safety: The message is ready, and isn't dropped before we are.
+        Ok(unsafe { (*self.channel.message.get()).assume_init_ref() }.clone())
+    }
+}
+
+impl<T> Drop for Receiver<'_, T> {
+    fn drop(&mut self) {
+        // AcqRel, so the last receiver only drops the message after all others
+        // are done cloning it.
+        if self.channel.remaining.fetch_sub(1, Ordering::AcqRel) == 1
+            && self.channel.ready.swap(false, Ordering::Acquire)
+        {
+            // Safety: Nobody else is left to look at the message. If it is
+            // sent after this, the channel drops it instead.
+            unsafe { (*self.channel.message.get()).assume_init_drop() };
+        }
+    }
+}
+
+impl<T> Channel<T> {
+    pub const fn new() -> Self {
+        Self {
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+            ready: AtomicBool::new(false),
+            sender_dropped: AtomicBool::new(false),
+            remaining: AtomicUsize::new(0),
+            receiving_threads: Vec::new(),
+        }
+    }
+
+    /// Splits the channel into a sender and `receivers` receivers, resetting
+    /// it to its empty state first.
+    pub fn split<'a>(&'a mut self, receivers: usize) -> (Sender<'a, T>, Vec<Receiver<'a, T>>) {
+        *self = Self::new();
+        *self.remaining.get_mut() = receivers;
+        self.receiving_threads = (0..receivers).map(|_| Mutex::new(None)).collect();
+        let channel = &*self;
+        (
+            Sender { channel },
+            (0..receivers)
+                .map(|index| Receiver { channel, index })
+                .collect(),
+        )
+    }
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        // Still set if the message was sent after the last receiver was
+        // dropped, or if there were no receivers at all.
+        if *self.ready.get_mut() {
+            unsafe { self.message.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::broadcast_one_shot::Channel;
+    use crate::one_shot::RecvError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Clone)]
+        struct DetectDrop(&'static str);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let mut channel = Channel::<DetectDrop>::new();
+        thread::scope(|s| {
+            let (sender, receivers) = channel.split(3);
+            let handles: Vec<_> = receivers
+                .into_iter()
+                .map(|receiver| s.spawn(move || receiver.receive().unwrap().0))
+                .collect();
+            sender.send(DetectDrop("hello world!"));
+            for handle in handles {
+                assert_eq!(handle.join().unwrap(), "hello world!");
+            }
+        });
+        // Three copies, and the original.
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn unreceived() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Clone)]
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let mut channel = Channel::new();
+        let (sender, receivers) = channel.split(2);
+        sender.send(DetectDrop);
+        drop(receivers);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+
+        let (sender, receivers) = channel.split(1);
+        drop(receivers);
+        sender.send(DetectDrop);
+        drop(channel);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn sender_dropped() {
+        let mut channel = Channel::<i32>::new();
+        let (sender, receivers) = channel.split(2);
+        drop(sender);
+        for receiver in receivers {
+            assert_eq!(receiver.receive(), Err(RecvError));
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use core::ops::{Deref, DerefMut};
+
+/// Pads and aligns a value to the size of a cache line, so it never shares a
+/// cache line with its neighbours.
+///
+/// Threads that write to values on the same cache line slow each other down,
+/// even if the values are unrelated ("false sharing"). Cache lines are 64 bytes
+/// on most processors, but Apple silicon works with pairs of 64-byte lines, so
+/// we use 128 bytes there.
+#[cfg_attr(
+    all(target_arch = "aarch64", target_vendor = "apple"),
+    repr(align(128))
+)]
+#[cfg_attr(
+    not(all(target_arch = "aarch64", target_vendor = "apple")),
+    repr(align(64))
+)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CachePadded<T>(pub T);
+
+impl<T> CachePadded<T> {
+    pub const fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for CachePadded<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> From<T> for CachePadded<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache_padded::CachePadded;
+    use std::mem;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test() {
+        assert!(mem::align_of::<CachePadded<u8>>() >= 64);
+        assert_eq!(
+            mem::size_of::<CachePadded<u8>>(),
+            mem::align_of::<CachePadded<u8>>()
+        );
+        let counters = [const { CachePadded::new(AtomicUsize::new(0)) }; 2];
+        let a = &counters[0] as *const _ as usize;
+        let b = &counters[1] as *const _ as usize;
+        assert!(b - a >= 64);
+        counters[1].fetch_add(1, Ordering::Relaxed);
+        assert_eq!(counters[1].load(Ordering::Relaxed), 1);
+    }
+}
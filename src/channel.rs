@@ -0,0 +1,252 @@
+//! A bounded channel for many producers and a single consumer.
+
+use crate::arc::Arc;
+use crate::cache_padded::CachePadded;
+use crate::futex::{wait_if, wake_all, wake_one};
+use crate::spin_lock::SpinLock;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+struct Shared<T> {
+    queue: SpinLock<VecDeque<T>>,
+    capacity: usize,
+    /// Number of `Sender`s.
+    senders: AtomicUsize,
+    receiver_alive: AtomicBool,
+    /// Bumped whenever an item is pushed or the last sender is dropped. The
+    /// receiver sleeps on this while the queue is empty.
+    ///
+    /// The counters are padded, so that the senders and the receiver don't
+    /// keep stealing each other's cache line.
+    pushed: CachePadded<AtomicU32>,
+    /// Bumped whenever an item is popped or the receiver is dropped. Senders
+    /// sleep on this while the queue is full.
+    popped: CachePadded<AtomicU32>,
+    /// The number of threads sleeping on `pushed` and `popped`, so that waking
+    /// them up can be skipped while there are none, which is the common case.
+    ///
+    /// A sleeping thread counts itself before checking the counter one last
+    /// time, and a waking thread bumps the counter before checking for
+    /// sleepers, both with SeqCst. So either the waking thread sees the
+    /// sleeper, or the sleeper sees the new counter value and doesn't sleep.
+    pushed_waiters: AtomicUsize,
+    popped_waiters: AtomicUsize,
+}
+
+impl<T> Shared<T> {
+    /// Bumps `pushed`, and wakes up the receiver if it's sleeping on it.
+    fn push(&self) {
+        // SeqCst also releases the pushed item to the receiver.
+        self.pushed.fetch_add(1, Ordering::SeqCst);
+        if self.pushed_waiters.load(Ordering::SeqCst) != 0 {
+            wake_one(&*self.pushed);
+        }
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Creates a channel that holds at most `capacity` items at once.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "capacity must be non-zero");
+    let shared = Arc::new(Shared {
+        queue: SpinLock::new(VecDeque::with_capacity(capacity)),
+        capacity,
+        senders: AtomicUsize::new(1),
+        receiver_alive: AtomicBool::new(true),
+        pushed: CachePadded::new(AtomicU32::new(0)),
+        popped: CachePadded::new(AtomicU32::new(0)),
+        pushed_waiters: AtomicUsize::new(0),
+        popped_waiters: AtomicUsize::new(0),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Sends `value`, blocking while the channel is full.
+    ///
+    /// Gives the value back if the receiver is gone.
+    pub fn send(&self, value: T) -> Result<(), T> {
+        loop {
+            // Read the counter before checking for room, so that a pop after
+            // the check changes it and keeps us from going to sleep.
+            let popped = self.shared.popped.load(Ordering::Acquire);
+            if !self.shared.receiver_alive.load(Ordering::Relaxed) {
+                return Err(value);
+            }
+            {
+                let mut queue = self.shared.queue.lock();
+                if queue.len() < self.shared.capacity {
+                    queue.push_back(value);
+                    drop(queue);
+                    self.shared.push();
+                    return Ok(());
+                }
+            }
+            let shared = &*self.shared;
+            shared.popped_waiters.fetch_add(1, Ordering::SeqCst);
+            wait_if(&*shared.popped, || {
+                shared.popped.load(Ordering::SeqCst) == popped
+            });
+            shared.popped_waiters.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::Release) == 1 {
+            // Wake up the receiver, so it notices there's nothing more to come.
+            self.shared.push();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives the next item, blocking while the channel is empty.
+    ///
+    /// Returns `None` once all senders are gone and the queue is drained.
+    pub fn recv(&self) -> Option<T> {
+        loop {
+            // Read the counter before checking for items, so that a push or
+            // disconnect after the check changes it and keeps us from going to
+            // sleep.
+            let pushed = self.shared.pushed.load(Ordering::Acquire);
+            // Check for senders before checking the queue: anything pushed by
+            // a sender happens before it is dropped.
+            let disconnected = self.shared.senders.load(Ordering::Acquire) == 0;
+            let item = self.shared.queue.lock().pop_front();
+            if let Some(item) = item {
+                self.shared.popped.fetch_add(1, Ordering::SeqCst);
+                if self.shared.popped_waiters.load(Ordering::SeqCst) != 0 {
+                    wake_one(&*self.shared.popped);
+                }
+                return Some(item);
+            }
+            if disconnected {
+                return None;
+            }
+            let shared = &*self.shared;
+            shared.pushed_waiters.fetch_add(1, Ordering::SeqCst);
+            wait_if(&*shared.pushed, || {
+                shared.pushed.load(Ordering::SeqCst) == pushed
+            });
+            shared.pushed_waiters.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_alive.store(false, Ordering::Relaxed);
+        // Wake up all blocked senders, so they can give their values back.
+        self.shared.popped.fetch_add(1, Ordering::SeqCst);
+        if self.shared.popped_waiters.load(Ordering::SeqCst) != 0 {
+            wake_all(&*self.shared.popped);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::channel::channel;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let (sender, receiver) = channel(4);
+        thread::scope(|s| {
+            for i in 0..4 {
+                let sender = sender.clone();
+                s.spawn(move || {
+                    for j in 0..100 {
+                        sender.send(i * 100 + j).unwrap();
+                    }
+                });
+            }
+            drop(sender);
+            let mut received: Vec<i32> = std::iter::from_fn(|| receiver.recv()).collect();
+            received.sort();
+            assert_eq!(received, (0..400).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn backpressure() {
+        let (sender, receiver) = channel(2);
+        let sent = AtomicBool::new(false);
+        thread::scope(|s| {
+            sender.send(1).unwrap();
+            sender.send(2).unwrap();
+            s.spawn(|| {
+                sender.send(3).unwrap();
+                sent.store(true, Ordering::Relaxed);
+            });
+            thread::sleep(Duration::from_millis(50));
+            // The channel is full, so the third send is blocked.
+            assert!(!sent.load(Ordering::Relaxed));
+            assert_eq!(receiver.recv(), Some(1));
+        });
+        assert!(sent.load(Ordering::Relaxed));
+        assert_eq!(receiver.recv(), Some(2));
+        assert_eq!(receiver.recv(), Some(3));
+    }
+
+    #[test]
+    fn capacity_one() {
+        // Both sides keep blocking on each other, so a lost wake-up would
+        // hang.
+        let (sender, receiver) = channel(1);
+        thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..10_000 {
+                    sender.send(i).unwrap();
+                }
+            });
+            for i in 0..10_000 {
+                assert_eq!(receiver.recv(), Some(i));
+            }
+            assert_eq!(receiver.recv(), None);
+        });
+    }
+
+    #[test]
+    fn shutdown() {
+        let (sender, receiver) = channel(2);
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                sender.send("last").unwrap();
+            });
+            assert_eq!(receiver.recv(), Some("last"));
+            // The receiver wakes up when the sender is dropped.
+            assert_eq!(receiver.recv(), None);
+        });
+
+        let (sender, receiver) = channel(1);
+        drop(receiver);
+        assert_eq!(sender.send(1), Err(1));
+    }
+}
@@ -0,0 +1,101 @@
+//! A `Vec` that any number of threads can push to at the same time, like a
+//! buffer that collects log records until they're written out.
+
+use crate::spin_lock::SpinLock;
+use ::alloc::vec::Vec;
+use core::mem;
+
+pub struct ConcurrentVec<T> {
+    items: SpinLock<Vec<T>>,
+}
+
+impl<T> ConcurrentVec<T> {
+    pub const fn new() -> Self {
+        Self {
+            items: SpinLock::new(Vec::new()),
+        }
+    }
+
+    pub fn push(&self, item: T) {
+        self.items.lock().push(item);
+    }
+
+    /// Returns the number of items, which other threads may change right
+    /// after.
+    pub fn len(&self) -> usize {
+        self.items.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Takes all items pushed so far, leaving the vector empty.
+    ///
+    /// The vector is swapped for an empty one while locked, so each item
+    /// pushed concurrently ends up either in the result or in the vector, and
+    /// can be taken by the next `drain`.
+    pub fn drain(&self) -> Vec<T> {
+        mem::take(&mut *self.items.lock())
+    }
+
+    pub fn into_inner(self) -> Vec<T> {
+        self.items.into_inner()
+    }
+}
+
+impl<T> Default for ConcurrentVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> FromIterator<T> for ConcurrentVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self {
+            items: SpinLock::new(iter.into_iter().collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::concurrent_vec::ConcurrentVec;
+    use std::thread;
+
+    #[test]
+    fn concurrent_push_and_drain() {
+        let vec = ConcurrentVec::new();
+        let mut drained = Vec::new();
+        thread::scope(|s| {
+            for i in 0..4 {
+                let vec = &vec;
+                s.spawn(move || {
+                    for j in 0..1_000 {
+                        vec.push(i * 1_000 + j);
+                    }
+                });
+            }
+            // Drain while the others are still pushing.
+            for _ in 0..100 {
+                drained.extend(vec.drain());
+                thread::yield_now();
+            }
+        });
+        drained.extend(vec.drain());
+        assert!(vec.is_empty());
+        drained.sort();
+        assert!(drained.into_iter().eq(0..4_000));
+    }
+
+    #[test]
+    fn from_iter() {
+        let vec: ConcurrentVec<_> = (1..=3).collect();
+        assert_eq!(vec.len(), 3);
+        vec.push(4);
+        assert_eq!(vec.drain(), [1, 2, 3, 4]);
+        assert_eq!(vec.len(), 0);
+        vec.push(5);
+        assert_eq!(vec.into_inner(), [5]);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::futex::{wait, wake_all, wake_one};
+use crate::mutex::MutexGuard;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+pub struct Condvar {
+    counter: AtomicU32,
+    num_waiters: AtomicUsize,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            counter: AtomicU32::new(0),
+            num_waiters: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn notify_one(&self) {
+        if self.num_waiters.load(Ordering::Relaxed) > 0 {
+            self.counter.fetch_add(1, Ordering::Relaxed);
+            wake_one(&self.counter);
+        }
+    }
+
+    pub fn notify_all(&self) {
+        if self.num_waiters.load(Ordering::Relaxed) > 0 {
+            self.counter.fetch_add(1, Ordering::Relaxed);
+            wake_all(&self.counter);
+        }
+    }
+
+    /// Unlocks the mutex, waits for a notification, and locks the mutex again
+    /// before returning.
+    ///
+    /// Like any condition variable, this might return without a
+    /// notification, so the awaited condition should be checked in a loop.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        // The waiter count is incremented while still holding the mutex, so a
+        // notifying thread that locks the mutex after us is guaranteed to see
+        // it.
+        self.num_waiters.fetch_add(1, Ordering::Relaxed);
+
+        // Take the counter snapshot before unlocking, so a notification that
+        // happens right after unlocking changes the counter and can't be
+        // missed.
+        let counter_value = self.counter.load(Ordering::Relaxed);
+
+        let mutex = guard.mutex;
+        drop(guard);
+
+        wait(&self.counter, counter_value);
+
+        self.num_waiters.fetch_sub(1, Ordering::Relaxed);
+
+        mutex.lock()
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::condvar::Condvar;
+    use crate::mutex::Mutex;
+    use std::collections::VecDeque;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let queue = Mutex::new(VecDeque::new());
+        let not_empty = Condvar::new();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                for i in 0..10 {
+                    queue.lock().push_back(i);
+                    not_empty.notify_one();
+                }
+            });
+
+            let mut received = Vec::new();
+            let mut q = queue.lock();
+            while received.len() < 10 {
+                match q.pop_front() {
+                    Some(item) => received.push(item),
+                    None => q = not_empty.wait(q),
+                }
+            }
+            assert_eq!(received, (0..10).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn notify_all() {
+        let ready = Mutex::new(false);
+        let condvar = Condvar::new();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut r = ready.lock();
+                    while !*r {
+                        r = condvar.wait(r);
+                    }
+                });
+            }
+            thread::sleep(Duration::from_millis(10));
+            *ready.lock() = true;
+            condvar.notify_all();
+        });
+    }
+}
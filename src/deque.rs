@@ -0,0 +1,361 @@
+//! A work-stealing deque, following the Chase-Lev algorithm with the memory
+//! orderings from "Correct and Efficient Work-Stealing for Weak Memory Models"
+//! by Lê, Pop, Cohen and Zappa Nardelli.
+//!
+//! The owning [`Worker`] pushes and pops at the bottom, like a stack, while any
+//! number of [`Stealer`]s take from the top. Only a pop or steal of the very
+//! last element needs a compare-and-exchange to decide who gets it.
+//!
+//! When the buffer is full, the worker moves the elements into a buffer twice
+//! the size. Stealers might still be reading the old buffer, so it's only freed
+//! once no hazard pointer protects it anymore.
+
+use crate::arc::Arc;
+use crate::cache_padded::CachePadded;
+use crate::hazard::HazardDomain;
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicIsize, AtomicPtr, Ordering};
+
+const MIN_CAPACITY: usize = 16;
+
+struct Buffer<T> {
+    /// A power of two, so positions wrap around with a mask.
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+impl<T> Buffer<T> {
+    fn alloc(capacity: usize) -> *mut Self {
+        let slots = (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect();
+        Box::into_raw(Box::new(Buffer { slots }))
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn slot(&self, pos: isize) -> *mut MaybeUninit<T> {
+        self.slots[pos as usize & (self.capacity() - 1)].get()
+    }
+
+    /// Safety: Only the worker may write, and only to a slot that is not in
+    /// use.
+    unsafe fn write(&self, pos: isize, value: T) {
+        unsafe { (*self.slot(pos)).write(value) };
+    }
+
+    /// Copies the value at `pos` out without taking ownership of it yet.
+    ///
+    /// Safety: The copy may only be assumed initialized once the caller has
+    /// claimed the position.
+    unsafe fn read(&self, pos: isize) -> MaybeUninit<T> {
+        // A stealer's read might race with the worker writing this slot, in
+        // which case the claim fails and the copy is discarded. A volatile
+        // read keeps the compiler from making any assumptions about it.
+        unsafe { ptr::read_volatile(self.slot(pos)) }
+    }
+}
+
+unsafe fn free_buffer<T>(buffer: *mut Buffer<T>) {
+    // Dropping the buffer doesn't drop any elements, as they're `MaybeUninit`.
+    drop(unsafe { Box::from_raw(buffer) });
+}
+
+struct Inner<T> {
+    /// The position of the next steal.
+    top: CachePadded<AtomicIsize>,
+    /// The position of the next push.
+    bottom: CachePadded<AtomicIsize>,
+    buffer: AtomicPtr<Buffer<T>>,
+    /// Protects buffers that were replaced while stealers might still read
+    /// them.
+    domain: HazardDomain,
+    _marker: PhantomData<T>,
+}
+
+/// Elements are moved between threads, but never shared.
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let top = *self.top.get_mut();
+        let bottom = *self.bottom.get_mut();
+        let buffer = *self.buffer.get_mut();
+        // Safety: Nobody else is left, so the elements between top and bottom
+        // are ours to drop, and nobody reads the buffer anymore.
+        unsafe {
+            for pos in top..bottom {
+                (*(*buffer).slot(pos)).assume_init_drop();
+            }
+            free_buffer(buffer);
+        }
+    }
+}
+
+/// The owning end of a work-stealing deque. There is only one, and it can't be
+/// shared between threads, but it can be moved to another thread.
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    /// Makes `Worker` `!Sync`, as pushing and popping are only safe from one
+    /// thread at a time.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> Worker<T> {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                top: CachePadded::new(AtomicIsize::new(0)),
+                bottom: CachePadded::new(AtomicIsize::new(0)),
+                buffer: AtomicPtr::new(Buffer::alloc(MIN_CAPACITY)),
+                domain: HazardDomain::new(),
+                _marker: PhantomData,
+            }),
+            _not_sync: PhantomData,
+        }
+    }
+
+    /// Creates a stealer for this deque, which can be cloned and shared with
+    /// other threads.
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Adds `value` at the bottom of the deque.
+    pub fn push(&self, value: T) {
+        let inner = &*self.inner;
+        let bottom = inner.bottom.load(Ordering::Relaxed);
+        let top = inner.top.load(Ordering::Acquire);
+        // Only we replace the buffer, so no need for a hazard pointer.
+        let mut buffer = inner.buffer.load(Ordering::Relaxed);
+        // Safety: The buffer is only freed after we replace it.
+        if bottom - top >= unsafe { (*buffer).capacity() } as isize {
+            buffer = self.grow(buffer, top, bottom);
+        }
+        // Safety: Stealers don't claim positions at or after bottom.
+        unsafe { (*buffer).write(bottom, value) };
+        // Release, so stealers that see the new bottom see the value.
+        inner.bottom.store(bottom + 1, Ordering::Release);
+    }
+
+    /// Moves the elements into a buffer twice the size, and returns it.
+    fn grow(&self, old: *mut Buffer<T>, top: isize, bottom: isize) -> *mut Buffer<T> {
+        // Safety: Only we replace the buffer, so `old` is still alive.
+        let new = Buffer::alloc(unsafe { (*old).capacity() } * 2);
+        for pos in top..bottom {
+            // Safety: Just moving bits around. The elements stay in the old
+            // buffer too, where stealers may still read them, but only the
+            // one that claims a position takes ownership.
+            unsafe { ptr::copy_nonoverlapping((*old).slot(pos), (*new).slot(pos), 1) };
+        }
+        // Release, so stealers that load the new buffer see its elements.
+        self.inner.buffer.store(new, Ordering::Release);
+        // Safety: The old buffer is unlinked, and we never touch it again.
+        unsafe { self.inner.domain.retire(old, free_buffer) };
+        new
+    }
+
+    /// Takes the element that was pushed last, if any.
+    pub fn pop(&self) -> Option<T> {
+        let inner = &*self.inner;
+        let bottom = inner.bottom.load(Ordering::Relaxed) - 1;
+        let buffer = inner.buffer.load(Ordering::Relaxed);
+        // Reserve the position first, then check whether stealers got there.
+        inner.bottom.store(bottom, Ordering::Relaxed);
+        // Pairs with the fence in `steal`: either the stealer sees the lowered
+        // bottom, or we see its raised top.
+        fence(Ordering::SeqCst);
+        let top = inner.top.load(Ordering::Relaxed);
+        if top > bottom {
+            // It was empty.
+            inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            return None;
+        }
+        // Safety: The position is reserved for us, unless it's the last
+        // element, which we claim below.
+        let value = unsafe { (*buffer).read(bottom) };
+        if top == bottom {
+            // The last element: race the stealers for it.
+            let won = inner
+                .top
+                .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+            inner.bottom.store(bottom + 1, Ordering::Relaxed);
+            if !won {
+                return None;
+            }
+        }
+        // Safety: The position is ours.
+        Some(unsafe { value.assume_init() })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let bottom = self.inner.bottom.load(Ordering::Relaxed);
+        let top = self.inner.top.load(Ordering::Relaxed);
+        bottom <= top
+    }
+}
+
+impl<T> Default for Worker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The stealing end of a work-stealing deque, which takes the elements that
+/// were pushed first.
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Stealer<T> {
+    /// Takes the element at the top of the deque, if any.
+    pub fn steal(&self) -> Option<T> {
+        let inner = &*self.inner;
+        let hazard = inner.domain.acquire();
+        loop {
+            let top = inner.top.load(Ordering::Acquire);
+            // Pairs with the fence in `pop`.
+            fence(Ordering::SeqCst);
+            let bottom = inner.bottom.load(Ordering::Acquire);
+            if top >= bottom {
+                return None;
+            }
+            // Loaded after bottom, so this is the buffer the element at `top`
+            // was pushed into, or a newer one it was copied to.
+            let buffer = hazard.protect(&inner.buffer);
+            // Safety: The buffer is protected, and we only assume the copy is
+            // initialized if we claim the position.
+            let value = unsafe { (*buffer).read(top) };
+            if inner
+                .top
+                .compare_exchange(top, top + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok()
+            {
+                // Safety: The position is ours.
+                return Some(unsafe { value.assume_init() });
+            }
+            // Another stealer, or the worker popping the last element, won.
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let top = self.inner.top.load(Ordering::Relaxed);
+        let bottom = self.inner.bottom.load(Ordering::Relaxed);
+        bottom <= top
+    }
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::deque::Worker;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn pop_lifo() {
+        let worker = Worker::new();
+        for i in 0..100 {
+            worker.push(i);
+        }
+        for i in (0..100).rev() {
+            assert_eq!(worker.pop(), Some(i));
+        }
+        assert_eq!(worker.pop(), None);
+        assert!(worker.is_empty());
+    }
+
+    #[test]
+    fn steal_fifo() {
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        for i in 0..100 {
+            worker.push(i);
+        }
+        for i in 0..50 {
+            assert_eq!(stealer.steal(), Some(i));
+        }
+        assert_eq!(worker.pop(), Some(99));
+        assert_eq!(stealer.clone().steal(), Some(50));
+        for _ in 51..99 {
+            assert!(stealer.steal().is_some());
+        }
+        assert_eq!(stealer.steal(), None);
+        assert!(stealer.is_empty());
+    }
+
+    #[test]
+    fn drops_remaining() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        for _ in 0..100 {
+            worker.push(DetectDrop);
+        }
+        drop(stealer.steal());
+        drop(worker.pop());
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+        drop(worker);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+        drop(stealer);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn stress() {
+        const N: usize = 100_000;
+        let worker = Worker::<usize>::new();
+        let seen: Vec<AtomicUsize> = (0..N).map(|_| AtomicUsize::new(0)).collect();
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                let stealer = worker.stealer();
+                let (seen, done) = (&seen, &done);
+                s.spawn(move || loop {
+                    match stealer.steal() {
+                        Some(i) => {
+                            seen[i].fetch_add(1, Ordering::Relaxed);
+                        }
+                        None if done.load(Ordering::Acquire) => break,
+                        None => thread::yield_now(),
+                    }
+                });
+            }
+            for i in 0..N {
+                worker.push(i);
+                if i % 3 == 0 {
+                    if let Some(j) = worker.pop() {
+                        seen[j].fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+            while let Some(j) = worker.pop() {
+                seen[j].fetch_add(1, Ordering::Relaxed);
+            }
+            done.store(true, Ordering::Release);
+        });
+        assert!(seen.iter().all(|n| n.load(Ordering::Relaxed) == 1));
+    }
+}
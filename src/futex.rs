@@ -0,0 +1,269 @@
+//! Futex-style wait and wake operations, emulated with thread parking.
+//!
+//! A thread waits on the address of an atomic variable, and is woken by
+//! another thread calling `wake_one` on the same address. Waiting threads are
+//! kept in queues in a fixed table of buckets, each protected by its own lock.
+//! The waiting thread checks the atomic's value while holding that lock, and
+//! the waking thread takes the same lock, so a wake-up can never get lost
+//! between the check and going to sleep.
+
+#[cfg(not(feature = "loom"))]
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+#[cfg(not(feature = "loom"))]
+use std::sync::Mutex;
+#[cfg(not(feature = "loom"))]
+use std::thread::{self, Thread};
+use std::time::Instant;
+
+#[cfg(not(feature = "loom"))]
+const NUM_BUCKETS: usize = 64;
+
+/// How a wait ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wake {
+    /// The condition didn't hold, or the deadline passed.
+    NotWoken,
+    Woken,
+    /// Woken by `wake_one_handoff`, which handed something over to this
+    /// thread in particular.
+    HandedOff,
+}
+
+#[cfg(not(feature = "loom"))]
+struct Waiter {
+    key: usize,
+    thread: Thread,
+    /// Points to a `Wake` (as `u32`) on the waiting thread's stack, which stays
+    /// `NotWoken` until the thread is woken. The waiting thread doesn't return
+    /// before it's set or it removed itself from the queue, so the pointer
+    /// stays valid for as long as the waiter is queued.
+    woken: *const AtomicU32,
+}
+
+/// Safety: The `woken` pointer is only dereferenced while the waiter is
+/// queued, during which the pointed-to flag is kept alive.
+#[cfg(not(feature = "loom"))]
+unsafe impl Send for Waiter {}
+
+#[cfg(not(feature = "loom"))]
+static BUCKETS: [Mutex<Vec<Waiter>>; NUM_BUCKETS] = [const { Mutex::new(Vec::new()) }; NUM_BUCKETS];
+
+#[cfg(not(feature = "loom"))]
+fn bucket(key: usize) -> &'static Mutex<Vec<Waiter>> {
+    // Atomics are at least a few bytes apart, so the lowest bits carry little
+    // information.
+    &BUCKETS[(key >> 2) % NUM_BUCKETS]
+}
+
+/// Parks the current thread in the queue for `key`, if `validate` returns
+/// true. `validate` is called with the queue locked. If a `deadline` is given,
+/// the thread removes itself from the queue once it passes.
+///
+/// Returns how the thread was woken, or `NotWoken` if `validate` returned
+/// false or the deadline passed.
+#[cfg(not(feature = "loom"))]
+fn park(key: usize, validate: impl FnOnce() -> bool, deadline: Option<Instant>) -> Wake {
+    let woken = AtomicU32::new(Wake::NotWoken as u32);
+    {
+        let mut queue = bucket(key).lock().unwrap();
+        if !validate() {
+            return Wake::NotWoken;
+        }
+        queue.push(Waiter {
+            key,
+            thread: thread::current(),
+            woken: &woken,
+        });
+    }
+    // `thread::park()` might return spuriously, so check the flag again after
+    // every wake-up.
+    let is_woken = || woken.load(Ordering::Acquire) != Wake::NotWoken as u32;
+    while !is_woken() {
+        let Some(deadline) = deadline else {
+            thread::park();
+            continue;
+        };
+        let now = Instant::now();
+        if now < deadline {
+            thread::park_timeout(deadline - now);
+            continue;
+        }
+        let mut queue = bucket(key).lock().unwrap();
+        // We might have been woken right before taking the lock.
+        if is_woken() {
+            break;
+        }
+        let i = queue
+            .iter()
+            .position(|waiter| ptr::eq(waiter.woken, &woken))
+            .unwrap();
+        queue.remove(i);
+        return Wake::NotWoken;
+    }
+    if woken.load(Ordering::Relaxed) == Wake::HandedOff as u32 {
+        Wake::HandedOff
+    } else {
+        Wake::Woken
+    }
+}
+
+/// Wakes up at most `max` threads parked in the queue for `key`, in the order
+/// in which they went to sleep. `callback` is called with the number of woken
+/// threads while the queue is still locked, so no thread can start parking in
+/// between, and before the woken threads can return. If it returns true, they
+/// return `HandedOff`. Returns the number of threads woken.
+#[cfg(not(feature = "loom"))]
+fn unpark(key: usize, max: usize, callback: impl FnOnce(usize) -> bool) -> usize {
+    let mut woken = Vec::new();
+    {
+        let mut queue = bucket(key).lock().unwrap();
+        let mut i = 0;
+        while i < queue.len() && woken.len() < max {
+            if queue[i].key == key {
+                woken.push(queue.remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        let wake = if callback(woken.len()) {
+            Wake::HandedOff
+        } else {
+            Wake::Woken
+        };
+        for waiter in &woken {
+            // Safety: The waiter was still queued when we took the lock, and
+            // can't return before this store, so its flag is alive. After
+            // this store, the waiting thread may return at any time, so we
+            // must not touch the flag anymore.
+            unsafe { (*waiter.woken).store(wake as u32, Ordering::Release) };
+        }
+    }
+    let n = woken.len();
+    for waiter in woken {
+        waiter.thread.unpark();
+    }
+    n
+}
+
+/// With loom, which can't model the queues, parking just yields to the other
+/// threads, as if the deadline had passed right away. Every waiting thread
+/// checks its condition again afterwards, so it ends up spinning instead.
+#[cfg(feature = "loom")]
+fn park(_key: usize, validate: impl FnOnce() -> bool, _deadline: Option<Instant>) -> Wake {
+    if validate() {
+        crate::sync::thread::yield_now();
+    }
+    Wake::NotWoken
+}
+
+/// With loom, no thread is ever parked, so there is nobody to wake up.
+#[cfg(feature = "loom")]
+fn unpark(_key: usize, _max: usize, callback: impl FnOnce(usize) -> bool) -> usize {
+    callback(0);
+    0
+}
+
+/// An atomic that threads can wait on.
+pub trait Futex {
+    type Value: PartialEq;
+
+    fn load_relaxed(&self) -> Self::Value;
+}
+
+impl Futex for AtomicU32 {
+    type Value = u32;
+
+    fn load_relaxed(&self) -> u32 {
+        self.load(Ordering::Relaxed)
+    }
+}
+
+impl Futex for AtomicUsize {
+    type Value = usize;
+
+    fn load_relaxed(&self) -> usize {
+        self.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(feature = "loom")]
+impl Futex for loom::sync::atomic::AtomicU32 {
+    type Value = u32;
+
+    fn load_relaxed(&self) -> u32 {
+        self.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(all(feature = "ordering-checks", debug_assertions, not(feature = "loom")))]
+impl Futex for crate::sync::atomic::AtomicU32 {
+    type Value = u32;
+
+    fn load_relaxed(&self) -> u32 {
+        self.load(Ordering::Relaxed)
+    }
+}
+
+fn key<A: Futex>(atomic: &A) -> usize {
+    atomic as *const A as usize
+}
+
+/// Blocks until woken up by `wake_one` or `wake_all`, unless `atomic` doesn't
+/// hold `expected` anymore.
+pub fn wait<A: Futex>(atomic: &A, expected: A::Value) {
+    park(key(atomic), || atomic.load_relaxed() == expected, None);
+}
+
+/// Blocks until woken up through `atomic`, unless `condition` returns false.
+/// `condition` is called while no thread can wake up through `atomic`.
+///
+/// Returns false if `condition` returned false, and true once woken.
+pub fn wait_if<A: Futex>(atomic: &A, condition: impl FnOnce() -> bool) -> bool {
+    park(key(atomic), condition, None) != Wake::NotWoken
+}
+
+/// Like `wait_if`, but gives up and returns false once `deadline` has passed.
+pub fn wait_if_until<A: Futex>(
+    atomic: &A,
+    condition: impl FnOnce() -> bool,
+    deadline: Instant,
+) -> bool {
+    park(key(atomic), condition, Some(deadline)) != Wake::NotWoken
+}
+
+/// Like `wait_if` or `wait_if_until`, but tells whether the thread was woken
+/// by `wake_one_handoff` handing something over to it.
+pub fn wait_for_handoff<A: Futex>(
+    atomic: &A,
+    condition: impl FnOnce() -> bool,
+    deadline: Option<Instant>,
+) -> Wake {
+    park(key(atomic), condition, deadline)
+}
+
+/// Wakes up one thread blocked in `wait` on `atomic`, if any.
+pub fn wake_one<A: Futex>(atomic: &A) {
+    unpark(key(atomic), 1, |_| false);
+}
+
+/// Like `wake_one`, but calls `f` with the number of woken threads (0 or 1)
+/// before any other thread can start waiting on `atomic`.
+pub fn wake_one_then<A: Futex>(atomic: &A, f: impl FnOnce(usize)) {
+    unpark(key(atomic), 1, |n| {
+        f(n);
+        false
+    });
+}
+
+/// Like `wake_one_then`, but if `f` returns true, the woken thread learns from
+/// `wait_for_handoff` that it was woken for a handoff. Anything `f` does
+/// happens before that thread returns.
+pub fn wake_one_handoff<A: Futex>(atomic: &A, f: impl FnOnce(usize) -> bool) {
+    unpark(key(atomic), 1, f);
+}
+
+/// Wakes up all threads blocked in `wait` on `atomic`.
+pub fn wake_all<A: Futex>(atomic: &A) {
+    unpark(key(atomic), usize::MAX, |_| false);
+}
@@ -0,0 +1,266 @@
+//! A minimal hazard pointer scheme, for safely freeing memory that other
+//! threads might still be reading through a shared `AtomicPtr`.
+//!
+//! A reader announces which pointer it is about to use by storing it in a
+//! hazard slot. A writer that unlinked a pointer hands it to
+//! [`HazardDomain::retire`], which only frees it once no slot refers to it
+//! anymore.
+
+use crate::spin_lock::SpinLock;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicPtr, Ordering};
+
+struct Slot {
+    ptr: AtomicPtr<()>,
+    active: AtomicBool,
+    /// The slot that was the head of the list before this one was pushed.
+    next: *const Slot,
+}
+
+// Safety: `next` is only written before the slot is shared.
+unsafe impl Sync for Slot {}
+
+struct Retired {
+    ptr: *mut (),
+    reclaim: unsafe fn(*mut ()),
+}
+
+/// Safety: `retire` requires the retired pointer to be safe to reclaim from
+/// any thread.
+unsafe impl Send for Retired {}
+
+pub struct HazardDomain {
+    /// A push-only list of slots, so they never move, and are only freed when
+    /// the domain is dropped.
+    slots: AtomicPtr<Slot>,
+    retired: SpinLock<Vec<Retired>>,
+}
+
+impl HazardDomain {
+    pub fn new() -> Self {
+        Self {
+            slots: AtomicPtr::new(ptr::null_mut()),
+            retired: SpinLock::new(Vec::new()),
+        }
+    }
+
+    /// Iterates over all slots that have been pushed so far.
+    fn slots(&self) -> impl Iterator<Item = &Slot> {
+        let mut next = self.slots.load(Ordering::Acquire) as *const Slot;
+        std::iter::from_fn(move || {
+            // Safety: Slots are never moved or freed while the domain is
+            // alive, and the Acquire load of the head (or the `next` of an
+            // earlier slot) makes them fully visible.
+            let slot = unsafe { next.as_ref()? };
+            next = slot.next;
+            Some(slot)
+        })
+    }
+
+    /// Claims a hazard slot, reusing a released one if possible. This never
+    /// blocks.
+    pub fn acquire(&self) -> HazardPointer<'_> {
+        for slot in self.slots() {
+            if slot
+                .active
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return HazardPointer { slot };
+            }
+        }
+        let slot = Box::into_raw(Box::new(Slot {
+            ptr: AtomicPtr::new(ptr::null_mut()),
+            active: AtomicBool::new(true),
+            next: ptr::null(),
+        }));
+        let mut head = self.slots.load(Ordering::Relaxed);
+        loop {
+            // Safety: The slot isn't shared until the exchange succeeds.
+            unsafe { (*slot).next = head };
+            match self
+                .slots
+                .compare_exchange_weak(head, slot, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => head = current,
+            }
+        }
+        // Safety: Slots are never moved or freed while the domain is alive.
+        HazardPointer {
+            slot: unsafe { &*slot },
+        }
+    }
+
+    /// Hands over an unlinked pointer, to be reclaimed by calling `reclaim`
+    /// once no hazard pointer protects it anymore.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been removed from every place readers could newly
+    /// load it from, it must be retired only once, and `reclaim(ptr)` must be
+    /// safe to call from any thread.
+    pub unsafe fn retire<T>(&self, ptr: *mut T, reclaim: unsafe fn(*mut T)) {
+        self.retired.lock().push(Retired {
+            ptr: ptr.cast(),
+            // Safety: `*mut T` and `*mut ()` are ABI-compatible.
+            reclaim: unsafe {
+                std::mem::transmute::<unsafe fn(*mut T), unsafe fn(*mut ())>(reclaim)
+            },
+        });
+        self.scan();
+    }
+
+    /// Reclaims every retired pointer that isn't protected.
+    fn scan(&self) {
+        // Pairs with the fence in `HazardPointer::protect`: either the reader
+        // sees that the pointer was unlinked, or we see its hazard.
+        fence(Ordering::SeqCst);
+        let protected: Vec<*mut ()> = self
+            .slots()
+            .map(|slot| slot.ptr.load(Ordering::Relaxed))
+            .filter(|ptr| !ptr.is_null())
+            .collect();
+        let reclaimable: Vec<Retired> = {
+            let mut retired = self.retired.lock();
+            let (reclaimable, deferred) =
+                retired.drain(..).partition(|r| !protected.contains(&r.ptr));
+            *retired = deferred;
+            reclaimable
+        };
+        // Reclaim outside of the lock, since reclaiming might retire more.
+        for r in reclaimable {
+            unsafe { (r.reclaim)(r.ptr) };
+        }
+    }
+}
+
+impl Default for HazardDomain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for HazardDomain {
+    fn drop(&mut self) {
+        // No hazard pointers can exist anymore, as they borrow the domain.
+        for r in self.retired.lock().drain(..) {
+            unsafe { (r.reclaim)(r.ptr) };
+        }
+        let mut next = *self.slots.get_mut();
+        while !next.is_null() {
+            // Safety: Every slot was allocated by `acquire` and is only freed
+            // here.
+            let slot = unsafe { Box::from_raw(next) };
+            next = slot.next as *mut Slot;
+        }
+    }
+}
+
+pub struct HazardPointer<'a> {
+    slot: &'a Slot,
+}
+
+impl HazardPointer<'_> {
+    /// Loads the pointer from `src` and protects it from being reclaimed,
+    /// until the next `protect`, `reset`, or until this hazard pointer is
+    /// dropped.
+    pub fn protect<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+        let mut ptr = src.load(Ordering::Relaxed);
+        loop {
+            self.slot.ptr.store(ptr.cast(), Ordering::Relaxed);
+            // Pairs with the fence in `HazardDomain::scan`.
+            fence(Ordering::SeqCst);
+            // If the pointer is still there, it can't have been retired
+            // before our hazard became visible.
+            let current = src.load(Ordering::Acquire);
+            if current == ptr {
+                return ptr;
+            }
+            ptr = current;
+        }
+    }
+
+    /// Stops protecting the current pointer.
+    pub fn reset(&self) {
+        self.slot.ptr.store(ptr::null_mut(), Ordering::Release);
+    }
+}
+
+impl Drop for HazardPointer<'_> {
+    fn drop(&mut self) {
+        self.reset();
+        self.slot.active.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hazard::HazardDomain;
+    use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+    use std::thread;
+
+    const ALIVE: usize = 0xa11fe;
+
+    static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Node(usize);
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            assert_eq!(self.0, ALIVE);
+            self.0 = 0;
+            NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    unsafe fn reclaim(ptr: *mut Node) {
+        drop(unsafe { Box::from_raw(ptr) });
+    }
+
+    #[test]
+    fn reuses_slots() {
+        let domain = HazardDomain::new();
+        let a = domain.acquire();
+        let b = domain.acquire();
+        drop(a);
+        let c = domain.acquire();
+        assert_eq!(domain.slots().count(), 2);
+        drop((b, c));
+        let _d = domain.acquire();
+        let _e = domain.acquire();
+        let _f = domain.acquire();
+        assert_eq!(domain.slots().count(), 3);
+    }
+
+    #[test]
+    fn test() {
+        let domain = HazardDomain::new();
+        let shared = AtomicPtr::new(Box::into_raw(Box::new(Node(ALIVE))));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let hazard = domain.acquire();
+                    for _ in 0..10_000 {
+                        let node = hazard.protect(&shared);
+                        // Safety: The node is protected, so it isn't freed.
+                        assert_eq!(unsafe { (*node).0 }, ALIVE);
+                    }
+                });
+            }
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..1_000 {
+                        let new = Box::into_raw(Box::new(Node(ALIVE)));
+                        let old = shared.swap(new, Ordering::SeqCst);
+                        unsafe { domain.retire(old, reclaim) };
+                    }
+                });
+            }
+        });
+        drop(domain);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2_000);
+        unsafe { reclaim(shared.into_inner()) };
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2_001);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::futex::{wait_if, wake_all};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts down from a fixed number, and releases everyone waiting once it
+/// reaches zero.
+///
+/// Unlike a `Barrier` or a `WaitGroup`, a latch can't be reused: once the count
+/// is zero, it stays zero, and `wait` keeps returning right away.
+pub struct CountdownLatch {
+    count: AtomicUsize,
+    /// The number of threads sleeping until the count reaches zero, so that
+    /// the last count down can skip waking them up if there are none.
+    num_waiters: AtomicUsize,
+}
+
+impl CountdownLatch {
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count: AtomicUsize::new(count),
+            num_waiters: AtomicUsize::new(0),
+        }
+    }
+
+    /// Counts down by one, releasing the waiting threads if this reaches zero.
+    /// Does nothing if the count already is zero.
+    pub fn count_down(&self) {
+        // Release, so the waiting threads see everything that happened before
+        // any of the count downs. SeqCst, like going to sleep in `wait`:
+        // either we see the sleeping thread, or it sees the new count.
+        if let Ok(1) = self
+            .count
+            .fetch_update(Ordering::SeqCst, Ordering::Relaxed, |n| n.checked_sub(1))
+        {
+            if self.num_waiters.load(Ordering::SeqCst) != 0 {
+                wake_all(&self.count);
+            }
+        }
+    }
+
+    /// Returns how far the latch still has to count down.
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until the count reaches zero, returning right away if it
+    /// already did.
+    pub fn wait(&self) {
+        loop {
+            let n = self.count.load(Ordering::Acquire);
+            if n == 0 {
+                return;
+            }
+            // Only the last count down wakes us up, but if the count changes
+            // before we're asleep, we don't go to sleep and check again.
+            self.num_waiters.fetch_add(1, Ordering::SeqCst);
+            wait_if(&self.count, || self.count.load(Ordering::SeqCst) == n);
+            self.num_waiters.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::latch::CountdownLatch;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let latch = CountdownLatch::new(8);
+        let counted = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    latch.wait();
+                    // Released exactly when the last worker counted down.
+                    assert_eq!(counted.load(Ordering::Relaxed), 8);
+                    assert_eq!(latch.count(), 0);
+                });
+            }
+            for i in 0..8 {
+                let (latch, counted) = (&latch, &counted);
+                s.spawn(move || {
+                    thread::sleep(Duration::from_millis(i * 5));
+                    counted.fetch_add(1, Ordering::Relaxed);
+                    latch.count_down();
+                });
+            }
+        });
+        // Stays released.
+        latch.wait();
+        latch.count_down();
+        assert_eq!(latch.count(), 0);
+    }
+
+    #[test]
+    fn zero() {
+        CountdownLatch::new(0).wait();
+    }
+}
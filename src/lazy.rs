@@ -0,0 +1,67 @@
+use crate::once::OnceCell;
+use std::cell::Cell;
+use std::ops::Deref;
+
+/// A value that is computed on first access, usable in statics.
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    init: Cell<Option<F>>,
+}
+
+/// Only the one thread that initializes the value takes the closure out, which
+/// may be a different thread than the one that created the `Lazy`.
+unsafe impl<T, F: Send> Sync for Lazy<T, F> where T: Send + Sync {}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceCell::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    /// Forces the evaluation of the value, and returns a reference to it.
+    pub fn force(lazy: &Self) -> &T {
+        lazy.cell.get_or_init(|| match lazy.init.take() {
+            Some(init) => init(),
+            None => panic!("Lazy instance has previously been poisoned"),
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+impl<T: Default> Default for Lazy<T> {
+    fn default() -> Self {
+        Self::new(T::default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lazy::Lazy;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE: Lazy<String> = Lazy::new(|| {
+            thread::sleep(Duration::from_millis(10));
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            "hello".to_string()
+        });
+        thread::scope(|s| {
+            for _ in 0..16 {
+                s.spawn(|| assert_eq!(*VALUE, "hello"));
+            }
+        });
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    }
+}
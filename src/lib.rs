@@ -1,3 +1,95 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#![cfg_attr(feature = "nightly", feature(coerce_unsized, set_ptr_value, unsize))]
+
+extern crate alloc;
+
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod adaptive_lock;
 pub mod arc;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod arc_swap;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod atomic_cell;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod barrier;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod broadcast_one_shot;
+pub mod cache_padded;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod channel;
+#[cfg(not(feature = "loom"))]
+pub mod concurrent_vec;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod condvar;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod deque;
+#[cfg(feature = "std")]
+// With loom, only `SpinLock` is left to wait on futexes.
+#[cfg_attr(feature = "loom", allow(dead_code))]
+mod futex;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod hazard;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod latch;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod lazy;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod mutex;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod notify;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod once;
+#[cfg(feature = "std")]
 pub mod one_shot;
+#[cfg(all(feature = "ordering-checks", debug_assertions, not(feature = "loom")))]
+mod ordering_checks;
+pub mod queue;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod rcu;
+#[cfg(feature = "std")]
+pub mod reentrant_spin_lock;
+#[cfg(not(feature = "loom"))]
+pub mod registry;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod renewable_one_shot;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod rw_spin_lock;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod semaphore;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod seq_lock;
+#[cfg(not(feature = "loom"))]
+pub mod shared;
 pub mod spin_lock;
+mod sync;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod wait_group;
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub mod weak_singleton;
+
+/// Only runs without `std`, to check that what's left works on its own.
+#[cfg(all(test, not(feature = "std")))]
+mod tests {
+    use crate::arc::Arc;
+    use crate::queue::ArrayQueue;
+    use crate::spin_lock::SpinLock;
+
+    #[test]
+    fn without_std() {
+        let queue = Arc::new(ArrayQueue::new(4));
+        let sum = Arc::new(SpinLock::new(0));
+        for i in 1..=4 {
+            queue.push(i).unwrap();
+        }
+        let (q, s) = (queue.clone(), sum.clone());
+        std::thread::spawn(move || {
+            while let Some(i) = q.pop() {
+                *s.lock() += i;
+            }
+        })
+        .join()
+        .unwrap();
+        assert_eq!(*sum.lock(), 10);
+        assert!(queue.pop().is_none());
+    }
+}
@@ -0,0 +1,341 @@
+use crate::futex::{wait_for_handoff, wake_one, wake_one_handoff, Wake};
+use crate::spin_lock::TryLockError;
+use std::cell::{Cell, UnsafeCell};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// The lock is held by a thread that was woken up to take it over directly
+/// from the unlocking thread, but hasn't noticed yet. Only that thread, which
+/// learns about it from `wait_for_handoff`, may take it.
+const HANDED_OFF: u32 = 3;
+
+/// How often, at most, the lock is handed off directly to a waiting thread.
+const FAIR_INTERVAL: Duration = Duration::from_micros(500);
+
+/// A lock that puts waiting threads to sleep.
+///
+/// Unlocking normally just releases the lock and wakes up a waiting thread,
+/// which then has to compete with any other thread trying to take the lock in
+/// the meantime. That's fast, but a thread that keeps relocking could starve
+/// the waiting ones. So every [`FAIR_INTERVAL`], unlocking instead hands the
+/// lock directly to the longest waiting thread.
+pub struct Mutex<T> {
+    /// 0: unlocked
+    /// 1: locked, no other threads waiting
+    /// 2: locked, other threads waiting
+    /// 3: handed off to a woken thread, other threads might be waiting
+    state: AtomicU32,
+    /// When the next unlock with waiting threads should hand off the lock.
+    /// Only accessed by the thread holding the lock.
+    next_fair_unlock: Cell<Option<Instant>>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for Mutex<T> where T: Send {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0), // unlocked state
+            next_fair_unlock: Cell::new(None),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self
+            .state
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // The lock was already locked. :(
+            lock_contended(&self.state, None);
+        }
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Result<MutexGuard<'_, T>, TryLockError> {
+        match self
+            .state
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+        {
+            Ok(_) => Ok(MutexGuard { mutex: self }),
+            Err(_) => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    /// Like `lock`, but gives up and returns `None` if the mutex couldn't be
+    /// locked within `timeout`.
+    pub fn lock_timeout(&self, timeout: Duration) -> Option<MutexGuard<'_, T>> {
+        if let Ok(guard) = self.try_lock() {
+            return Some(guard);
+        }
+        let deadline = Instant::now() + timeout;
+        lock_contended(&self.state, Some(deadline)).then_some(MutexGuard { mutex: self })
+    }
+
+    /// Returns whether it's time to hand off the lock to a waiting thread on
+    /// unlock, instead of releasing it. Must only be called while locked.
+    fn fair_unlock_due(&self) -> bool {
+        let now = Instant::now();
+        match self.next_fair_unlock.get() {
+            Some(next) if now < next => false,
+            next => {
+                self.next_fair_unlock.set(Some(now + FAIR_INTERVAL));
+                // Start the first interval only when there's contention.
+                next.is_some()
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        // We don't need to lock: the exclusive reference guarantees that
+        // nobody else is holding a guard.
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Returns false if `deadline` passed before the lock could be taken.
+fn lock_contended(state: &AtomicU32, deadline: Option<Instant>) -> bool {
+    let mut spin_count = 0;
+
+    // Only spin while the lock is held without waiters. If there are waiting
+    // threads, we'd better join them right away instead of competing.
+    while state.load(Ordering::Relaxed) == 1 && spin_count < 100 {
+        spin_count += 1;
+        std::hint::spin_loop();
+    }
+
+    if state
+        .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok()
+    {
+        return true;
+    }
+
+    loop {
+        let s = state.load(Ordering::Relaxed);
+        // Since we can't know whether there are other waiters, we set the
+        // state to 2 whenever we take the lock or go to sleep, so the
+        // unlocking thread knows to wake one of us up.
+        if s == 0 {
+            if state
+                .compare_exchange(s, 2, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return true;
+            }
+            continue;
+        }
+        if s == 1
+            && state
+                .compare_exchange(1, 2, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            continue;
+        }
+        // The lock is held, or handed off to another thread.
+        let condition = || matches!(state.load(Ordering::Relaxed), 2 | HANDED_OFF);
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            // We leave the state at 2 even if we were the only waiter. We
+            // can't know whether others are still sleeping, and a needless
+            // wake call on unlock is harmless.
+            return false;
+        }
+        if wait_for_handoff(state, condition, deadline) == Wake::HandedOff {
+            // The lock was handed to us alone, and stays handed off until we
+            // take it. Acquire synchronizes with the unlocking thread's store.
+            let s = state.swap(2, Ordering::Acquire);
+            debug_assert_eq!(s, HANDED_OFF);
+            return true;
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    pub(crate) mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        let state = &self.mutex.state;
+        // Only make the wake call when some thread announced it's sleeping.
+        if state
+            .compare_exchange(1, 0, Ordering::Release, Ordering::Relaxed)
+            .is_ok()
+        {
+            return;
+        }
+        // The state is 2, which only we can change now.
+        if self.mutex.fair_unlock_due() {
+            // Keep the lock locked, and let the woken thread take it. Decided
+            // while no thread can start waiting, so that if nobody was woken
+            // up, the lock is released instead of handed off to nobody.
+            wake_one_handoff(state, |woken| {
+                let new_state = if woken == 1 { HANDED_OFF } else { 0 };
+                state.store(new_state, Ordering::Release);
+                woken == 1
+            });
+        } else {
+            state.store(0, Ordering::Release);
+            wake_one(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mutex::Mutex;
+    use crate::spin_lock::TryLockError;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test() {
+        let counter = Mutex::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        *counter.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.into_inner(), 80_000);
+    }
+
+    #[test]
+    fn try_lock() {
+        let mut m = Mutex::new(1);
+        {
+            let g = m.try_lock().unwrap();
+            assert!(matches!(m.try_lock(), Err(TryLockError::WouldBlock)));
+            drop(g);
+        }
+        *m.get_mut() += 1;
+        assert_eq!(*m.try_lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn lock_timeout() {
+        let m = Mutex::new(0);
+        thread::scope(|s| {
+            let g = m.lock();
+            let timed_out = s.spawn(|| m.lock_timeout(Duration::from_millis(50)).is_none());
+            assert!(timed_out.join().unwrap());
+            let waiter = s.spawn(|| *m.lock_timeout(Duration::from_secs(10)).unwrap() += 1);
+            thread::sleep(Duration::from_millis(50));
+            drop(g);
+            waiter.join().unwrap();
+        });
+        assert_eq!(*m.lock(), 1);
+        // The timed out waiter left the state consistent.
+        assert_eq!(m.state.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn hands_off_to_waiter() {
+        let m = Mutex::new(0);
+        // The first unlock with a waiter only starts the fairness interval, the
+        // next one (long enough after) hands off the lock.
+        for round in 1..=2 {
+            thread::scope(|s| {
+                let g = m.lock();
+                s.spawn(|| *m.lock() += 1);
+                thread::sleep(Duration::from_millis(50));
+                drop(g);
+                if round == 2 {
+                    // The waiter can't be overtaken, so by the time we get the
+                    // lock, it must have had its turn.
+                    assert_eq!(*m.lock(), 2);
+                }
+            });
+        }
+        assert_eq!(*m.lock(), 2);
+    }
+
+    #[test]
+    fn handoff_stress() {
+        // Holding the lock longer than the fairness interval makes nearly
+        // every unlock a handoff, while other threads keep waking up too.
+        let m = Mutex::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..50 {
+                        let mut g = m.lock();
+                        *g += 1;
+                        thread::sleep(Duration::from_micros(600));
+                    }
+                });
+            }
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..1_000 {
+                        *m.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(m.into_inner(), 2_200);
+    }
+
+    #[test]
+    fn no_starvation() {
+        let m = Mutex::new(0);
+        thread::scope(|s| {
+            let mut g = m.lock();
+            let waiter = s.spawn(|| *m.lock() += 1);
+            let start = Instant::now();
+            // Keep relocking right away, which would leave the waiter no chance
+            // to take the lock if it was never handed off.
+            while !waiter.is_finished() {
+                thread::sleep(Duration::from_micros(100));
+                drop(g);
+                g = m.lock();
+            }
+            assert!(start.elapsed() < Duration::from_secs(1));
+        });
+    }
+
+    #[test]
+    fn sleeps_under_long_hold() {
+        let m = Mutex::new(0);
+        thread::scope(|s| {
+            let g = m.lock();
+            let waiter = s.spawn(|| *m.lock() += 1);
+            thread::sleep(Duration::from_millis(100));
+            // The waiter gave up spinning and announced it is sleeping.
+            assert_eq!(m.state.load(Ordering::Relaxed), 2);
+            assert!(!waiter.is_finished());
+            drop(g);
+            waiter.join().unwrap();
+        });
+        assert_eq!(*m.lock(), 1);
+    }
+}
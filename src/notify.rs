@@ -0,0 +1,102 @@
+use crate::futex::{wait_if, wake_all, wake_one_then};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Wakes up waiting threads, without any data attached.
+///
+/// A `notify_one` without any waiting thread isn't lost: it leaves a permit,
+/// which makes the next `notified` return right away. Permits don't add up;
+/// there is at most one.
+pub struct Notify {
+    /// 1 if a permit is stored, 0 otherwise.
+    permit: AtomicU32,
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Self {
+            permit: AtomicU32::new(0),
+        }
+    }
+
+    /// Blocks until notified, or returns right away if a permit was stored.
+    pub fn notified(&self) {
+        if self.permit.swap(0, Ordering::Acquire) == 1 {
+            return;
+        }
+        // Check for (and take) a permit again while no `notify_one` can run,
+        // so a permit stored after the check above isn't missed.
+        wait_if(&self.permit, || self.permit.swap(0, Ordering::Acquire) == 0);
+    }
+
+    /// Wakes up one waiting thread, or stores a permit if no thread is
+    /// waiting.
+    pub fn notify_one(&self) {
+        wake_one_then(&self.permit, |woken| {
+            if woken == 0 {
+                // No thread can start waiting before we stored this.
+                self.permit.store(1, Ordering::Release);
+            }
+        });
+    }
+
+    /// Wakes up all waiting threads. Doesn't store a permit.
+    pub fn notify_all(&self) {
+        wake_all(&self.permit);
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::notify::Notify;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn notify_before_wait() {
+        let notify = Notify::new();
+        notify.notify_one();
+        notify.notify_one();
+        // Returns right away, but only once.
+        notify.notified();
+        assert_eq!(notify.permit.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn notify_after_wait() {
+        let notify = Notify::new();
+        thread::scope(|s| {
+            let t = s.spawn(|| notify.notified());
+            thread::sleep(Duration::from_millis(10));
+            notify.notify_one();
+            t.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn notify_all() {
+        let notify = Notify::new();
+        let woken = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    notify.notified();
+                    woken.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            // `notify_all` doesn't leave a permit, so repeat it for threads
+            // that weren't waiting yet.
+            while woken.load(Ordering::Relaxed) < 4 {
+                thread::sleep(Duration::from_millis(10));
+                notify.notify_all();
+            }
+        });
+        assert_eq!(woken.load(Ordering::Relaxed), 4);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::futex::{wait, wake_all};
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+const COMPLETE: u32 = 2;
+
+/// Runs a piece of initialization exactly once.
+///
+/// If the initialization closure panics, the `Once` goes back to its
+/// incomplete state instead of being poisoned, and the next call to
+/// [`call_once`](Self::call_once) (possibly a thread that was waiting) tries
+/// again.
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        // Acquire to see everything the initializing thread did.
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Runs `f` if no call has completed yet. If another thread is running its
+    /// closure at the moment, this blocks until that one is done.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+        loop {
+            match self.state.compare_exchange(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => {
+                    let reset = ResetOnPanic(&self.state);
+                    f();
+                    std::mem::forget(reset);
+                    self.state.store(COMPLETE, Ordering::Release);
+                    wake_all(&self.state);
+                    return;
+                }
+                Err(COMPLETE) => return,
+                Err(_) => wait(&self.state, RUNNING),
+            }
+        }
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Puts the state back to incomplete and wakes up the waiting threads, if the
+/// initialization closure panics.
+struct ResetOnPanic<'a>(&'a AtomicU32);
+
+impl Drop for ResetOnPanic<'_> {
+    fn drop(&mut self) {
+        self.0.store(INCOMPLETE, Ordering::Release);
+        wake_all(self.0);
+    }
+}
+
+/// A cell that can be written only once, by whichever thread gets to
+/// initialize it first.
+pub struct OnceCell<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// The value is shared between threads once initialized, and may have been
+/// created on another thread than the one that drops it.
+unsafe impl<T> Sync for OnceCell<T> where T: Send + Sync {}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self {
+        Self {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            // Safety: The value was initialized, and is never written again.
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        self.once.call_once(|| {
+            let value = f();
+            // Safety: Only the one thread running this closure accesses the
+            // value, and nobody reads it before the `Once` completes.
+            unsafe { (*self.value.get()).write(value) };
+        });
+        // Safety: `call_once` only returns once the value is initialized.
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for OnceCell<T> {
+    fn drop(&mut self) {
+        if *self.once.state.get_mut() == COMPLETE {
+            // Safety: The value was initialized, and we own it exclusively.
+            unsafe { self.value.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::once::{Once, OnceCell};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let once = Once::new();
+        let calls = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..16 {
+                s.spawn(|| {
+                    once.call_once(|| {
+                        thread::sleep(Duration::from_millis(10));
+                        calls.fetch_add(1, Ordering::Relaxed);
+                    });
+                    // Everyone observes the completed initialization.
+                    assert_eq!(calls.load(Ordering::Relaxed), 1);
+                });
+            }
+        });
+        assert!(once.is_completed());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn once_cell() {
+        let cell = OnceCell::new();
+        let calls = AtomicUsize::new(0);
+        assert_eq!(cell.get(), None);
+        thread::scope(|s| {
+            for i in 0..16 {
+                let (cell, calls) = (&cell, &calls);
+                s.spawn(move || {
+                    let value = cell.get_or_init(|| {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                        i.to_string()
+                    });
+                    assert_eq!(cell.get(), Some(value));
+                });
+            }
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn retry_after_panic() {
+        let cell = OnceCell::new();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            cell.get_or_init(|| panic!("initialization failed"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.get_or_init(|| 1), &1);
+    }
+}
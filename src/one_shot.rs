@@ -1,91 +1,387 @@
+use crate::spin_lock::SpinLock;
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::thread::{self, Thread};
+use crate::sync::{self, const_fn, hint};
 use std::cell::UnsafeCell;
-use std::marker::PhantomData;
-use std::mem::MaybeUninit;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::thread::{self, Thread};
+use std::error::Error;
+use std::fmt;
+use std::mem::{self, MaybeUninit};
+use std::ptr;
+
+/// Returned by `receive` when the sender was dropped without sending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sender dropped without sending")
+    }
+}
+
+impl Error for RecvError {}
 
 pub struct Sender<'a, T> {
     channel: &'a Channel<T>,
-    receiving_thread: Thread,
 }
 
+/// The receiving half of a channel, which may be moved to (and receive on)
+/// another thread than the one that split the channel.
 pub struct Receiver<'a, T> {
     channel: &'a Channel<T>,
-    _no_send: PhantomData<*const ()>,
+}
+
+/// A receiver that hands a message that was sent but never received to a
+/// closure when it is dropped, instead of silently dropping the message.
+///
+/// Created by [`Receiver::on_drop_pending`].
+pub struct DrainReceiver<'a, T, F: FnOnce(T)> {
+    channel: &'a Channel<T>,
+    on_drop: Option<F>,
 }
 
 pub struct Channel<T> {
     message: UnsafeCell<MaybeUninit<T>>,
+    /// Set once a message has been written into the channel, so a second
+    /// sender can't overwrite it.
+    in_use: AtomicBool,
     ready: AtomicBool,
+    /// Set when the sender is dropped, whether it sent a message or not.
+    sender_dropped: AtomicBool,
+    /// The thread blocked in `receive`, if any, to be unparked by `send`.
+    receiving_thread: SpinLock<Option<Thread>>,
+    /// Set when the receiver is dropped, whether it received or not.
+    receiver_dropped: AtomicBool,
+    /// The thread blocked in `send_sync`, if any, to be unparked when the
+    /// receiver is dropped.
+    sending_thread: SpinLock<Option<Thread>>,
 }
 
 unsafe impl<T> Sync for Channel<T> where T: Send {}
 
 impl<T> Sender<'_, T> {
+    /// Panics if the channel already holds a message, e.g. one created with
+    /// [`Channel::new_ready`].
     pub fn send(self, message: T) {
+        if sync::swap(&self.channel.in_use, true, Ordering::Relaxed) {
+            panic!("can't send more than one message!");
+        }
+        unsafe { (*self.channel.message.get()).write(message) };
+        self.channel.ready.store(true, Ordering::Release);
+        // Dropping `self` wakes up the receiver.
+    }
+
+    /// Like `send`, but instead of panicking if the channel already holds a
+    /// message, or silently leaving the message in the channel if the receiver
+    /// is gone, gives back the sender and the message.
+    pub fn try_send(self, message: T) -> Result<(), (Self, T)> {
+        if self.channel.receiver_dropped.load(Ordering::Relaxed)
+            || sync::swap(&self.channel.in_use, true, Ordering::Relaxed)
+        {
+            return Err((self, message));
+        }
         unsafe { (*self.channel.message.get()).write(message) };
         self.channel.ready.store(true, Ordering::Release);
-        self.receiving_thread.unpark();
+        Ok(())
+    }
+
+    /// Like `send`, but blocks until the receiver is gone, which makes this a
+    /// synchronous handoff.
+    ///
+    /// Returns the message if the receiver was dropped without taking it.
+    pub fn send_sync(self, message: T) -> Result<(), T> {
+        // Register before sending, so the receiver can find us once it has
+        // seen the message.
+        *self.channel.sending_thread.lock() = Some(thread::current());
+        if sync::swap(&self.channel.in_use, true, Ordering::Relaxed) {
+            panic!("can't send more than one message!");
+        }
+        unsafe { (*self.channel.message.get()).write(message) };
+        self.channel.ready.store(true, Ordering::Release);
+        self.channel.wake_receiver();
+        while !self.channel.receiver_dropped.load(Ordering::Acquire) {
+            thread::park();
+        }
+        // The receiver is gone, so nobody else touches the message anymore.
+        if sync::swap(&self.channel.ready, false, Ordering::Acquire) {
+            // Safety: We've just checked (and reset) the ready flag.
+            Err(unsafe { (*self.channel.message.get()).assume_init_read() })
+        } else {
+            Ok(())
+        }
     }
 }
 
-impl<T> Receiver<'_, T> {
+impl<T> Drop for Sender<'_, T> {
+    fn drop(&mut self) {
+        self.channel.sender_dropped.store(true, Ordering::Release);
+        self.channel.wake_receiver();
+    }
+}
+
+impl<'a, T> Receiver<'a, T> {
     pub fn is_ready(&self) -> bool {
         self.channel.ready.load(Ordering::Relaxed)
     }
 
-    pub fn receive(self) -> T {
+    /// Returns a reference to the message if it has arrived, without
+    /// receiving it.
+    ///
+    /// The reference borrows the receiver, so it must be dropped before the
+    /// message can be received. `T` must be `Sync`, since the receiver might be
+    /// shared between threads.
+    pub fn peek(&self) -> Option<&T>
+    where
+        T: Sync,
+    {
+        if self.channel.ready.load(Ordering::Acquire) {
+            // Safety: Only `receive` resets the ready flag, which can't happen
+            // while the receiver is borrowed.
+            Some(unsafe { (*self.channel.message.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Blocks until the message arrives, or returns an error if the sender is
+    /// dropped without sending one.
+    pub fn receive(self) -> Result<T, RecvError> {
+        self.channel.receive()
+    }
+
+    /// Like `receive`, but checks for the message up to `spins` times before
+    /// going to sleep. This saves the cost of parking and unparking if the
+    /// message is about to arrive, without spinning for long if it isn't.
+    pub fn receive_spin(self, spins: u32) -> Result<T, RecvError> {
+        for _ in 0..spins {
+            // The sender sets the ready flag before it is dropped, so if it's
+            // dropped, the check below is final.
+            let sender_dropped = self.channel.sender_dropped.load(Ordering::Acquire);
+            // Only check with a swap once it looks ready, so spinning doesn't
+            // keep taking the cache line from the sender.
+            if self.channel.ready.load(Ordering::Relaxed)
+                && sync::swap(&self.channel.ready, false, Ordering::Acquire)
+            {
+                // Safety: We've just checked (and reset) the ready flag.
+                return Ok(unsafe { (*self.channel.message.get()).assume_init_read() });
+            }
+            if sender_dropped {
+                break;
+            }
+            hint::spin_loop();
+        }
+        self.channel.receive()
+    }
+
+    /// Like `receive`, but moves the message straight into `slot`, which can
+    /// live anywhere, e.g. on the heap. This avoids moving a large message
+    /// through the stack.
+    ///
+    /// Whatever was in `slot` before is overwritten without being dropped.
+    pub fn receive_into(self, slot: &mut MaybeUninit<T>) -> Result<&mut T, RecvError> {
+        self.channel.wait_ready()?;
+        // Safety: `wait_ready` has just checked (and reset) the ready flag, so
+        // we may move the message out of the channel.
+        unsafe {
+            ptr::copy_nonoverlapping((*self.channel.message.get()).as_ptr(), slot.as_mut_ptr(), 1);
+            Ok(slot.assume_init_mut())
+        }
+    }
+
+    /// Wraps the receiver so that `f` is called with the message if it was
+    /// sent, but not received, by the time the receiver is dropped.
+    pub fn on_drop_pending<F: FnOnce(T)>(self, f: F) -> DrainReceiver<'a, T, F> {
+        let channel = self.channel;
+        // The receiver lives on in the `DrainReceiver`.
+        mem::forget(self);
+        DrainReceiver {
+            channel,
+            on_drop: Some(f),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<'_, T> {
+    fn drop(&mut self) {
+        self.channel.drop_receiver();
+    }
+}
+
+impl<T, F: FnOnce(T)> DrainReceiver<'_, T, F> {
+    pub fn is_ready(&self) -> bool {
+        self.channel.ready.load(Ordering::Relaxed)
+    }
+
+    pub fn receive(self) -> Result<T, RecvError> {
+        // Resets the ready flag, so dropping `self` afterwards finds nothing.
+        self.channel.receive()
+    }
+}
+
+impl<T, F: FnOnce(T)> Drop for DrainReceiver<'_, T, F> {
+    fn drop(&mut self) {
+        if let Some(on_drop) = self.on_drop.take() {
+            if sync::swap(&self.channel.ready, false, Ordering::Acquire) {
+                // Safety: We've just checked (and reset) the ready flag.
+                on_drop(unsafe { (*self.channel.message.get()).assume_init_read() });
+            }
+        }
+        self.channel.drop_receiver();
+    }
+}
+
+/// Blocks until one of the receivers can receive without blocking, because
+/// its message arrived or its sender was dropped, and returns its index. If
+/// several can, returns the lowest index.
+///
+/// Doesn't receive anything itself, so the caller can go on to receive on the
+/// chosen receiver.
+///
+/// Panics if `receivers` is empty, since that would block forever.
+pub fn select<T>(receivers: &[Receiver<'_, T>]) -> usize {
+    assert!(!receivers.is_empty(), "can't select from no receivers");
+    // As in `wait_ready`, a sender that missed our handle has already set its
+    // flags, which the locks make visible to the checks below.
+    for receiver in receivers {
+        *receiver.channel.receiving_thread.lock() = Some(thread::current());
+    }
+    loop {
+        // Check all channels again after every wake-up, which might be
+        // spurious, or might come from any of the senders.
+        if let Some(index) = receivers.iter().position(|receiver| {
+            receiver.channel.ready.load(Ordering::Acquire)
+                || receiver.channel.sender_dropped.load(Ordering::Acquire)
+        }) {
+            return index;
+        }
+        thread::park();
+    }
+}
+
+impl<T> Channel<T> {
+    const_fn! {
+        pub const fn new() -> Self {
+            Self {
+                message: UnsafeCell::new(MaybeUninit::uninit()),
+                in_use: AtomicBool::new(false),
+                ready: AtomicBool::new(false),
+                sender_dropped: AtomicBool::new(false),
+                receiving_thread: SpinLock::new(None),
+                receiver_dropped: AtomicBool::new(false),
+                sending_thread: SpinLock::new(None),
+            }
+        }
+
+        /// Creates a channel that already holds `value`, so that the receiver
+        /// returned by [`split`](Self::split) gets it without any send.
+        pub const fn new_ready(value: T) -> Self {
+            Self {
+                message: UnsafeCell::new(MaybeUninit::new(value)),
+                in_use: AtomicBool::new(true),
+                ready: AtomicBool::new(true),
+                sender_dropped: AtomicBool::new(false),
+                receiving_thread: SpinLock::new(None),
+                receiver_dropped: AtomicBool::new(false),
+                sending_thread: SpinLock::new(None),
+            }
+        }
+    }
+
+    /// Returns the channel to its empty state, dropping a message that was
+    /// sent but never received.
+    pub fn reset(&mut self) {
+        // Dropping the old channel drops the message, if there is one.
+        *self = Self::new();
+    }
+
+    fn wake_receiver(&self) {
+        if let Some(thread) = &*self.receiving_thread.lock() {
+            thread.unpark();
+        }
+    }
+
+    fn drop_receiver(&self) {
+        self.receiver_dropped.store(true, Ordering::Release);
+        if let Some(thread) = &*self.sending_thread.lock() {
+            thread.unpark();
+        }
+    }
+
+    fn receive(&self) -> Result<T, RecvError> {
+        self.wait_ready()?;
+        // Safety: `wait_ready` has just checked (and reset) the ready flag.
+        Ok(unsafe { (*self.message.get()).assume_init_read() })
+    }
+
+    /// Blocks until the message arrives and resets the ready flag, after
+    /// which the caller must take the message out.
+    fn wait_ready(&self) -> Result<(), RecvError> {
+        // If `send` looks for our handle before we stored it, it has already
+        // set the ready flag, which the lock makes visible to the check below.
+        *self.receiving_thread.lock() = Some(thread::current());
         // Remember that `thread::park()` might return spuriously. (Or because something
         // other than our send method called `unpark()`.) This means that we cannot
         // assume that the ready flag has been set when `park()` returns. So, we
         // need to use a loop to check the flag again after getting unparked.
-        while !self.channel.ready.swap(false, Ordering::Acquire) {
+        while !sync::swap(&self.ready, false, Ordering::Acquire) {
+            if self.sender_dropped.load(Ordering::Acquire) {
+                // The sender sets the ready flag before it is dropped, so this
+                // check is final.
+                if !sync::swap(&self.ready, false, Ordering::Acquire) {
+                    return Err(RecvError);
+                }
+                break;
+            }
             thread::park();
         }
-        // Safety: We've just checked (and reset) the ready flag.
-        unsafe { (*self.channel.message.get()).assume_init_read() }
+        Ok(())
     }
-}
 
-impl<T> Channel<T> {
-    pub const fn new() -> Self {
-        Self {
-            message: UnsafeCell::new(MaybeUninit::uninit()),
-            ready: AtomicBool::new(false),
-        }
+    /// Splits the channel into a sender and a receiver.
+    ///
+    /// The channel is reset to its empty state, except that a message that is
+    /// still waiting in it is kept and handed to the new receiver. Use
+    /// [`reset`](Self::reset) to discard a waiting message.
+    pub fn split<'a>(&'a mut self) -> (Sender<'a, T>, Receiver<'a, T>) {
+        *self = if sync::swap(&self.ready, false, Ordering::Relaxed) {
+            // Safety: We've just checked (and reset) the ready flag, so
+            // dropping the old channel won't drop the message again.
+            Self::new_ready(unsafe { self.message.get_mut().assume_init_read() })
+        } else {
+            Self::new()
+        };
+        (Sender { channel: self }, Receiver { channel: self })
     }
+}
 
-    pub fn split<'a>(&'a mut self) -> (Sender<'a, T>, Receiver<'a, T>) {
-        *self = Self::new();
-        (
-            Sender {
-                channel: self,
-                receiving_thread: thread::current(),
-            },
-            Receiver {
-                channel: self,
-                _no_send: PhantomData,
-            },
-        )
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl<T> Drop for Channel<T> {
     fn drop(&mut self) {
-        // We don’t need to use an atomic operation to check the atomic ready flag,
-        // because an object can only be dropped if it is fully owned by whichever
-        // thread is dropping it
-        if *self.ready.get_mut() {
+        // We don’t need to synchronize with other threads to check the atomic ready
+        // flag, because an object can only be dropped if it is fully owned by
+        // whichever thread is dropping it. The halves borrowed it mutably, so
+        // whatever ended that borrow (joining the sender's thread, say) already
+        // made the sender's writes to the message and the flag visible here.
+        // And only the flag decides: each message is dropped here exactly if it
+        // was sent and nobody took it out since.
+        if self.ready.load(Ordering::Relaxed) {
             // The same holds for Unsafe Cell
             unsafe { self.message.get_mut().assume_init_drop() }
         }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "loom")))]
 mod tests {
-    use crate::one_shot::Channel;
+    use crate::one_shot::{self, Channel, Receiver, RecvError, Sender};
+    use std::mem::MaybeUninit;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
     use std::thread;
+    use std::time::Duration;
 
     #[test]
     fn test() {
@@ -95,7 +391,345 @@ mod tests {
             s.spawn(move || {
                 sender.send("hello world!");
             });
-            assert_eq!(receiver.receive(), "hello world!");
+            assert_eq!(receiver.receive(), Ok("hello world!"));
+        });
+    }
+
+    #[test]
+    fn peek() {
+        let mut channel = Channel::new();
+        let (sender, receiver) = channel.split();
+        assert_eq!(receiver.peek(), None);
+        sender.send(String::from("hello world!"));
+        assert_eq!(receiver.peek().map(String::as_str), Some("hello world!"));
+        assert_eq!(receiver.receive().unwrap(), "hello world!");
+    }
+
+    #[test]
+    fn send_sync() {
+        let mut channel = Channel::new();
+        let receiving = AtomicBool::new(false);
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                receiving.store(true, Ordering::Relaxed);
+                assert_eq!(receiver.receive(), Ok("hello world!"));
+            });
+            assert_eq!(sender.send_sync("hello world!"), Ok(()));
+            assert!(receiving.load(Ordering::Relaxed));
+        });
+
+        let (sender, receiver) = channel.split();
+        drop(receiver);
+        assert_eq!(sender.send_sync("never received"), Err("never received"));
+    }
+
+    #[test]
+    fn sender_dropped() {
+        let mut channel = Channel::<i32>::new();
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || {
+                thread::sleep(std::time::Duration::from_millis(10));
+                drop(sender);
+            });
+            assert_eq!(receiver.receive(), Err(RecvError));
+        });
+    }
+
+    #[test]
+    fn receive_on_other_thread() {
+        let mut channel = Channel::new();
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            let t = s.spawn(move || receiver.receive());
+            thread::sleep(std::time::Duration::from_millis(10));
+            sender.send("hello world!");
+            assert_eq!(t.join().unwrap(), Ok("hello world!"));
+        });
+    }
+
+    #[test]
+    fn receive_spin() {
+        let mut channel = Channel::new();
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || sender.send(1));
+            assert_eq!(receiver.receive_spin(u32::MAX), Ok(1));
+        });
+        // The message was there before the receiver could go to sleep.
+        assert!(channel.receiving_thread.lock().is_none());
+
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                sender.send(2);
+            });
+            assert_eq!(receiver.receive_spin(10), Ok(2));
+        });
+        // The receiver gave up spinning, and went to sleep.
+        assert!(channel.receiving_thread.lock().is_some());
+
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || drop(sender));
+            assert_eq!(receiver.receive_spin(10), Err(RecvError));
+        });
+    }
+
+    #[test]
+    fn receive_into() {
+        let mut channel = Channel::new();
+        let mut slot = Box::<[u64; 4096]>::new_uninit();
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || sender.send(std::array::from_fn(|i| i as u64)));
+            let message = receiver.receive_into(&mut slot).unwrap();
+            assert_eq!(message[4095], 4095);
+        });
+        // Safety: `receive_into` succeeded.
+        let message = unsafe { slot.assume_init() };
+        assert!(message.iter().enumerate().all(|(i, &x)| x == i as u64));
+
+        let (sender, receiver) = channel.split();
+        drop(sender);
+        let mut slot = MaybeUninit::uninit();
+        assert!(matches!(receiver.receive_into(&mut slot), Err(RecvError)));
+    }
+
+    #[test]
+    fn halves_are_send() {
+        // Both halves can be moved into scoped threads, so there's no need for
+        // a separate `Send` variant of `split`.
+        fn assert_send<T: Send>() {}
+        assert_send::<Sender<'_, String>>();
+        assert_send::<Receiver<'_, String>>();
+    }
+
+    #[test]
+    fn try_send() {
+        let mut channel = Channel::new();
+        let (sender, receiver) = channel.split();
+        assert!(sender.try_send(1).is_ok());
+        drop(receiver);
+        // The first message was never received, so the channel is still in use.
+        let (sender, receiver) = channel.split();
+        let (sender, message) = sender.try_send(2).unwrap_err();
+        assert_eq!(message, 2);
+        drop(sender);
+        assert_eq!(receiver.receive(), Ok(1));
+
+        let (sender, receiver) = channel.split();
+        drop(receiver);
+        assert_eq!(sender.try_send(3).unwrap_err().1, 3);
+    }
+
+    #[test]
+    fn select() {
+        let mut channels: [Channel<&str>; 3] = Default::default();
+        let [a, b, c] = &mut channels;
+        let (sa, ra) = a.split();
+        let (sb, rb) = b.split();
+        let (sc, rc) = c.split();
+        let receivers = [ra, rb, rc];
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                sb.send("hello world!");
+            });
+            assert_eq!(one_shot::select(&receivers), 1);
+        });
+        // The lowest index wins once several channels are ready, and a dropped
+        // sender counts too.
+        drop(sc);
+        assert_eq!(one_shot::select(&receivers), 1);
+        sa.send("first");
+        assert_eq!(one_shot::select(&receivers), 0);
+        let [_, rb, rc] = receivers;
+        assert_eq!(rb.receive(), Ok("hello world!"));
+        assert_eq!(rc.receive(), Err(RecvError));
+    }
+
+    #[test]
+    fn new_ready() {
+        let mut channel = Channel::new_ready(42);
+        let (_, receiver) = channel.split();
+        assert!(receiver.is_ready());
+        assert_eq!(receiver.receive(), Ok(42));
+    }
+
+    #[test]
+    fn new_ready_dropped_unreceived() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let channel = Channel::new_ready(DetectDrop);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        drop(channel);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn dropped_unreceived() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        for round in 1..=100 {
+            let mut channel = Channel::new();
+            thread::scope(|s| {
+                let (sender, receiver) = channel.split();
+                s.spawn(move || sender.send(DetectDrop));
+                drop(receiver);
+            });
+            assert_eq!(NUM_DROPS.load(Ordering::Relaxed), round - 1);
+            drop(channel);
+            assert_eq!(NUM_DROPS.load(Ordering::Relaxed), round);
+        }
+    }
+
+    #[test]
+    fn dropped_never_sent() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let mut channel = Channel::<DetectDrop>::new();
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || drop(sender));
+            assert_eq!(receiver.receive().err(), Some(RecvError));
+        });
+        drop(channel);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn on_drop_pending() {
+        let mut channel = Channel::new();
+        let mut pending = None;
+        let (sender, receiver) = channel.split();
+        let receiver = receiver.on_drop_pending(|message| pending = Some(message));
+        sender.send("in flight");
+        assert!(receiver.is_ready());
+        drop(receiver);
+        assert_eq!(pending, Some("in flight"));
+    }
+
+    #[test]
+    fn on_drop_pending_received() {
+        let mut channel = Channel::new();
+        let mut pending = None;
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            let receiver = receiver.on_drop_pending(|message| pending = Some(message));
+            s.spawn(move || sender.send("hello world!"));
+            assert_eq!(receiver.receive(), Ok("hello world!"));
+        });
+        assert_eq!(pending, None);
+    }
+
+    #[test]
+    fn split_pending() {
+        let mut channel = Channel::new();
+        let (sender, receiver) = channel.split();
+        sender.send(42);
+        drop(receiver);
+
+        let (sender, receiver) = channel.split();
+        let channel = receiver.channel;
+        assert!(!channel.sender_dropped.load(Ordering::Relaxed));
+        assert!(!channel.receiver_dropped.load(Ordering::Relaxed));
+        drop(sender);
+        assert_eq!(receiver.receive(), Ok(42));
+    }
+
+    #[test]
+    fn reset() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop(&'static str);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let mut channel = Channel::new();
+        let (sender, _) = channel.split();
+        sender.send(DetectDrop("never received"));
+        channel.reset();
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || sender.send(DetectDrop("hello world!")));
+            assert_eq!(receiver.receive().unwrap().0, "hello world!");
+        });
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+    }
+}
+
+/// Model tests, which loom runs under every possible interleaving of the
+/// threads, with the `loom` feature.
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use crate::one_shot::{self, Channel, RecvError};
+    use loom::cell::UnsafeCell;
+    use loom::thread;
+
+    /// Reads a message that was created, and so written, on the sending
+    /// thread. Loom catches the read if it isn't ordered after the write.
+    fn read(message: UnsafeCell<i32>) -> i32 {
+        message.with(|value| unsafe { *value })
+    }
+
+    // The receiver always runs on the spawned thread: the sender unparks it,
+    // which loom doesn't expect while the thread is blocked in `join`.
+
+    #[test]
+    fn send_and_receive() {
+        loom::model(|| {
+            // Loom's threads must be `'static`, so the channel is leaked.
+            let channel = Box::leak(Box::new(Channel::new()));
+            let (sender, receiver) = channel.split();
+            let t = thread::spawn(move || read(receiver.receive().unwrap()));
+            sender.send(UnsafeCell::new(42));
+            assert_eq!(t.join().unwrap(), 42);
+        });
+    }
+
+    #[test]
+    fn select() {
+        loom::model(|| {
+            let (a, b) = (Channel::<i32>::new(), Channel::new());
+            let (sender_a, receiver_a) = Box::leak(Box::new(a)).split();
+            let (sender_b, receiver_b) = Box::leak(Box::new(b)).split();
+            let t = thread::spawn(move || one_shot::select(&[receiver_a, receiver_b]));
+            sender_b.send(1);
+            assert_eq!(t.join().unwrap(), 1);
+            drop(sender_a);
+        });
+    }
+
+    #[test]
+    fn sender_dropped() {
+        loom::model(|| {
+            let channel = Box::leak(Box::new(Channel::<i32>::new()));
+            let (sender, receiver) = channel.split();
+            let t = thread::spawn(move || receiver.receive());
+            drop(sender);
+            assert_eq!(t.join().unwrap(), Err(RecvError));
         });
     }
 }
@@ -0,0 +1,552 @@
+//! Checks that the primitives built on [`crate::sync`] order memory accesses
+//! the way they promise to, in debug builds with the `ordering-checks` feature.
+//!
+//! `sync::atomic` then points here, to atomics that do what core's do, but also
+//! track which operations happen before which, with vector clocks. Each thread
+//! has a clock that counts, for every thread, how many of its releases happened
+//! before the current point. A release store publishes the storing thread's
+//! clock on the atomic, and an acquire load that reads it joins it into the
+//! loading thread's clock. This follows the ordering that is actually passed to
+//! each operation, including relaxed ones followed by fences, like in
+//! `Arc::drop`.
+//!
+//! A value wrapped in `Checked` then asserts on every access that the last
+//! write to it happened before, and for writes, that every read since did too.
+//! Unlike a real data race, a missing ordering fails the check on every run and
+//! on any hardware. Weakening an `Acquire` to `Relaxed` is caught even on x86,
+//! where the hardware wouldn't reorder anything.
+//!
+//! The model is simpler than the real one: a load always reads the latest
+//! value, so bugs that need an older value to be read can go unnoticed. Loom
+//! covers those. Synchronization that doesn't go through these atomics, like
+//! spawning and joining threads, is invisible to the clocks, so tests carry
+//! clocks across it with `handoff`.
+
+use std::cell::RefCell;
+use std::sync::{Mutex, PoisonError};
+
+/// For every thread, by index, the number of its releases that happened
+/// before some point.
+#[derive(Clone, Default)]
+struct VectorClock(Vec<u64>);
+
+impl VectorClock {
+    const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    fn get(&self, thread: usize) -> u64 {
+        self.0.get(thread).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, thread: usize, time: u64) {
+        if self.0.len() <= thread {
+            self.0.resize(thread + 1, 0);
+        }
+        self.0[thread] = time;
+    }
+
+    fn join(&mut self, other: &Self) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        for (time, &other) in self.0.iter_mut().zip(&other.0) {
+            *time = (*time).max(other);
+        }
+    }
+}
+
+struct ThreadClock {
+    index: usize,
+    /// Everything that happened before the current point of the thread.
+    now: VectorClock,
+    /// What relaxed loads read so far, which an acquire fence acquires.
+    fence_acquire: VectorClock,
+    /// What happened before the last release fence, which relaxed stores
+    /// publish.
+    fence_release: VectorClock,
+}
+
+impl ThreadClock {
+    fn new() -> Self {
+        static NEXT_INDEX: core::sync::atomic::AtomicUsize =
+            core::sync::atomic::AtomicUsize::new(0);
+        let index = NEXT_INDEX.fetch_add(1, Ordering::Relaxed);
+        let mut now = VectorClock::new();
+        // Starts at 1, so that even an empty clock doesn't cover the first
+        // accesses.
+        now.set(index, 1);
+        Self {
+            index,
+            now,
+            fence_acquire: VectorClock::new(),
+            fence_release: VectorClock::new(),
+        }
+    }
+
+    fn time(&self) -> u64 {
+        self.now.get(self.index)
+    }
+
+    /// Moves on after a release, so that what comes after isn't covered by it.
+    fn tick(&mut self) {
+        self.now.set(self.index, self.time() + 1);
+    }
+}
+
+thread_local! {
+    static THREAD: RefCell<ThreadClock> = RefCell::new(ThreadClock::new());
+}
+
+fn with_thread<R>(f: impl FnOnce(&mut ThreadClock) -> R) -> Option<R> {
+    // While thread locals are being destroyed, the clock may be gone already,
+    // and then there's nothing to track.
+    THREAD.try_with(|thread| f(&mut thread.borrow_mut())).ok()
+}
+
+fn is_acquire(ordering: Ordering) -> bool {
+    matches!(
+        ordering,
+        Ordering::Acquire | Ordering::AcqRel | Ordering::SeqCst
+    )
+}
+
+fn is_release(ordering: Ordering) -> bool {
+    matches!(
+        ordering,
+        Ordering::Release | Ordering::AcqRel | Ordering::SeqCst
+    )
+}
+
+/// Reads the clock of an atomic, for a load or the read of a read-modify-write
+/// operation.
+fn acquire(clock: &VectorClock, ordering: Ordering) {
+    with_thread(|thread| {
+        if is_acquire(ordering) {
+            thread.now.join(clock);
+        } else {
+            thread.fence_acquire.join(clock);
+        }
+    });
+}
+
+/// Replaces the clock of an atomic, for a store.
+fn release(clock: &mut VectorClock, ordering: Ordering) {
+    with_thread(|thread| {
+        if is_release(ordering) {
+            *clock = thread.now.clone();
+            thread.tick();
+        } else {
+            *clock = thread.fence_release.clone();
+        }
+    });
+}
+
+/// Adds to the clock of an atomic, for the write of a read-modify-write
+/// operation, which continues the release sequence of the value it replaces.
+fn release_rmw(clock: &mut VectorClock, ordering: Ordering) {
+    with_thread(|thread| {
+        if is_release(ordering) {
+            clock.join(&thread.now);
+            thread.tick();
+        } else {
+            clock.join(&thread.fence_release);
+        }
+    });
+}
+
+/// The clock of an atomic: what an acquire load that reads its current value
+/// synchronizes with.
+struct AtomicClock(Mutex<VectorClock>);
+
+impl AtomicClock {
+    const fn new() -> Self {
+        Self(Mutex::new(VectorClock::new()))
+    }
+
+    /// Runs `op` with the clock locked, so that no other operation on the same
+    /// atomic can come between an operation and its effect on the clocks.
+    fn with<R>(&self, op: impl FnOnce(&mut VectorClock) -> R) -> R {
+        op(&mut self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// Atomics with the same interface as core's, as far as the crate uses it.
+pub(crate) mod atomic {
+    use super::{acquire, is_acquire, is_release, release, release_rmw, with_thread, AtomicClock};
+    pub(crate) use core::sync::atomic::Ordering;
+
+    pub(crate) fn fence(ordering: Ordering) {
+        core::sync::atomic::fence(ordering);
+        with_thread(|thread| {
+            if is_acquire(ordering) {
+                let read = thread.fence_acquire.clone();
+                thread.now.join(&read);
+            }
+            if is_release(ordering) {
+                thread.fence_release = thread.now.clone();
+                thread.tick();
+            }
+        });
+    }
+
+    macro_rules! atomics {
+        ($($atomic:ident($value:ty),)*) => {
+            $(
+                pub(crate) struct $atomic {
+                    value: core::sync::atomic::$atomic,
+                    clock: AtomicClock,
+                }
+
+                // Not every atomic uses every operation.
+                #[allow(dead_code)]
+                impl $atomic {
+                    pub(crate) const fn new(value: $value) -> Self {
+                        Self {
+                            value: core::sync::atomic::$atomic::new(value),
+                            clock: AtomicClock::new(),
+                        }
+                    }
+
+                    pub(crate) fn get_mut(&mut self) -> &mut $value {
+                        self.value.get_mut()
+                    }
+
+                    pub(crate) fn load(&self, ordering: Ordering) -> $value {
+                        self.clock.with(|clock| {
+                            let value = self.value.load(ordering);
+                            acquire(clock, ordering);
+                            value
+                        })
+                    }
+
+                    pub(crate) fn store(&self, value: $value, ordering: Ordering) {
+                        self.clock.with(|clock| {
+                            self.value.store(value, ordering);
+                            release(clock, ordering);
+                        })
+                    }
+
+                    pub(crate) fn swap(&self, value: $value, ordering: Ordering) -> $value {
+                        self.rmw(ordering, |atomic| atomic.swap(value, ordering))
+                    }
+
+                    pub(crate) fn compare_exchange(
+                        &self,
+                        current: $value,
+                        new: $value,
+                        success: Ordering,
+                        failure: Ordering,
+                    ) -> Result<$value, $value> {
+                        self.clock.with(|clock| {
+                            let result = self.value.compare_exchange(current, new, success, failure);
+                            if result.is_ok() {
+                                acquire(clock, success);
+                                release_rmw(clock, success);
+                            } else {
+                                acquire(clock, failure);
+                            }
+                            result
+                        })
+                    }
+
+                    pub(crate) fn compare_exchange_weak(
+                        &self,
+                        current: $value,
+                        new: $value,
+                        success: Ordering,
+                        failure: Ordering,
+                    ) -> Result<$value, $value> {
+                        self.compare_exchange(current, new, success, failure)
+                    }
+
+                    fn rmw(
+                        &self,
+                        ordering: Ordering,
+                        op: impl FnOnce(&core::sync::atomic::$atomic) -> $value,
+                    ) -> $value {
+                        self.clock.with(|clock| {
+                            let old = op(&self.value);
+                            acquire(clock, ordering);
+                            release_rmw(clock, ordering);
+                            old
+                        })
+                    }
+                }
+            )*
+        };
+    }
+
+    macro_rules! arithmetic {
+        ($($atomic:ident($value:ty),)*) => {
+            $(
+                #[allow(dead_code)]
+                impl $atomic {
+                    pub(crate) fn fetch_add(&self, value: $value, ordering: Ordering) -> $value {
+                        self.rmw(ordering, |atomic| atomic.fetch_add(value, ordering))
+                    }
+
+                    pub(crate) fn fetch_sub(&self, value: $value, ordering: Ordering) -> $value {
+                        self.rmw(ordering, |atomic| atomic.fetch_sub(value, ordering))
+                    }
+                }
+            )*
+        };
+    }
+
+    atomics! {
+        AtomicBool(bool),
+        AtomicU32(u32),
+        AtomicU64(u64),
+        AtomicUsize(usize),
+    }
+
+    arithmetic! {
+        AtomicU32(u32),
+        AtomicU64(u64),
+        AtomicUsize(usize),
+    }
+}
+
+use atomic::Ordering;
+
+/// A number that checks that every access to it is ordered after the last
+/// write, and every write after all reads since, by the happens-before the
+/// atomics track. Creating it counts as a write, and so does dropping it.
+///
+/// The number itself is stored in a relaxed atomic, so that a failed check
+/// never turns into an actual data race.
+#[cfg(test)]
+pub(crate) struct Checked {
+    value: core::sync::atomic::AtomicUsize,
+    accesses: Mutex<Accesses>,
+}
+
+#[cfg(test)]
+struct Accesses {
+    /// The thread that wrote last, and its time then.
+    write: (usize, u64),
+    /// For every thread, its time at its last read since the last write.
+    reads: VectorClock,
+}
+
+#[cfg(test)]
+impl Checked {
+    pub(crate) fn new(value: usize) -> Self {
+        let write = with_thread(|thread| (thread.index, thread.time())).unwrap_or_default();
+        Self {
+            value: core::sync::atomic::AtomicUsize::new(value),
+            accesses: Mutex::new(Accesses {
+                write,
+                reads: VectorClock::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn read(&self) -> usize {
+        let mut accesses = self.accesses.lock().unwrap_or_else(PoisonError::into_inner);
+        with_thread(|thread| {
+            let (writer, time) = accesses.write;
+            assert!(
+                thread.now.get(writer) >= time,
+                "ordering check failed: read on thread {} isn't ordered after the last write, on thread {writer}",
+                thread.index,
+            );
+            accesses.reads.set(thread.index, thread.time());
+        });
+        self.value.load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub(crate) fn write(&self, value: usize) {
+        self.check_write();
+        self.value
+            .store(value, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    fn check_write(&self) {
+        let mut accesses = self.accesses.lock().unwrap_or_else(PoisonError::into_inner);
+        with_thread(|thread| {
+            let (writer, time) = accesses.write;
+            assert!(
+                thread.now.get(writer) >= time,
+                "ordering check failed: write on thread {} isn't ordered after the last write, on thread {writer}",
+                thread.index,
+            );
+            for (reader, &time) in accesses.reads.0.iter().enumerate() {
+                assert!(
+                    thread.now.get(reader) >= time,
+                    "ordering check failed: write on thread {} isn't ordered after a read on thread {reader}",
+                    thread.index,
+                );
+            }
+            accesses.write = (thread.index, thread.time());
+            accesses.reads = VectorClock::new();
+        });
+    }
+}
+
+#[cfg(test)]
+impl Drop for Checked {
+    fn drop(&mut self) {
+        // Don't turn a failed check into an abort, by failing another one
+        // while unwinding.
+        if !std::thread::panicking() {
+            self.check_write();
+        }
+    }
+}
+
+/// A thread's clock, taken to another thread, for synchronization that the
+/// clocks can't see otherwise, like spawning and joining threads.
+#[cfg(test)]
+pub(crate) struct Handoff(VectorClock);
+
+#[cfg(test)]
+pub(crate) fn handoff() -> Handoff {
+    let clock = with_thread(|thread| {
+        let clock = thread.now.clone();
+        thread.tick();
+        clock
+    });
+    Handoff(clock.unwrap_or_default())
+}
+
+#[cfg(test)]
+impl Handoff {
+    pub(crate) fn receive(self) {
+        with_thread(|thread| thread.now.join(&self.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arc::Arc;
+    use crate::one_shot::Channel;
+    use crate::ordering_checks::atomic::{AtomicBool, Ordering};
+    use crate::ordering_checks::{handoff, Checked};
+    use crate::spin_lock::SpinLock;
+    use std::thread::{self, Scope};
+
+    /// Spawns a thread, and returns a function that joins it, both carrying
+    /// the clocks over like spawning and joining would.
+    fn spawn<'scope, T: Send + 'scope>(
+        s: &'scope Scope<'scope, '_>,
+        f: impl FnOnce() -> T + Send + 'scope,
+    ) -> impl FnOnce() -> T + 'scope {
+        let parent = handoff();
+        let thread = s.spawn(move || {
+            parent.receive();
+            (f(), handoff())
+        });
+        move || {
+            let (result, child) = thread.join().unwrap();
+            child.receive();
+            result
+        }
+    }
+
+    fn publish(flag_ordering: Ordering, load_ordering: Ordering) {
+        let value = Checked::new(0);
+        let ready = AtomicBool::new(false);
+        thread::scope(|s| {
+            let join = spawn(s, || {
+                value.write(1);
+                ready.store(true, flag_ordering);
+            });
+            while !ready.load(load_ordering) {
+                thread::yield_now();
+            }
+            assert_eq!(value.read(), 1);
+            join();
+        });
+    }
+
+    #[test]
+    fn release_acquire() {
+        publish(Ordering::Release, Ordering::Acquire);
+    }
+
+    #[test]
+    #[should_panic = "ordering check failed"]
+    fn relaxed_store() {
+        publish(Ordering::Relaxed, Ordering::Acquire);
+    }
+
+    #[test]
+    #[should_panic = "ordering check failed"]
+    fn relaxed_load() {
+        publish(Ordering::Release, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn spin_lock() {
+        let lock = SpinLock::new(Checked::new(0));
+        let increment = || {
+            for _ in 0..1_000 {
+                let value = lock.lock();
+                value.write(value.read() + 1);
+            }
+        };
+        thread::scope(|s| {
+            let join = spawn(s, increment);
+            increment();
+            join();
+        });
+        assert_eq!(lock.lock().read(), 2_000);
+    }
+
+    #[test]
+    fn one_shot() {
+        let mut channel = Channel::new();
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            let join = spawn(s, move || sender.send(Checked::new(1)));
+            assert_eq!(receiver.receive().unwrap().read(), 1);
+            join();
+        });
+    }
+
+    #[test]
+    fn arc_drop() {
+        // Whichever thread drops its `Arc` last drops the value, which must
+        // come after the other thread's read.
+        for _ in 0..100 {
+            let a = Arc::new(Checked::new(1));
+            let b = a.clone();
+            thread::scope(|s| {
+                let join = spawn(s, move || {
+                    assert_eq!(b.read(), 1);
+                    drop(b);
+                });
+                assert_eq!(a.read(), 1);
+                drop(a);
+                join();
+            });
+        }
+    }
+
+    #[test]
+    fn get_mut_after_upgrade() {
+        // The upgraded `Arc` is dropped before `get_mut` can succeed, and the
+        // read through it must come before the write through `get_mut`.
+        for _ in 0..100 {
+            let mut a = Arc::new(Checked::new(1));
+            let weak = Arc::downgrade(&a);
+            thread::scope(|s| {
+                let join = spawn(s, move || {
+                    let upgraded = weak.upgrade().unwrap();
+                    drop(weak);
+                    assert_eq!(upgraded.read(), 1);
+                });
+                loop {
+                    if let Some(value) = Arc::get_mut(&mut a) {
+                        value.write(2);
+                        break;
+                    }
+                    thread::yield_now();
+                }
+                join();
+            });
+        }
+    }
+}
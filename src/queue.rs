@@ -0,0 +1,243 @@
+//! A fixed-capacity lock-free queue for many producers and many consumers,
+//! following Dmitry Vyukov's bounded MPMC queue.
+//!
+//! Every slot has a stamp that tells whose turn it is. Positions are stamps
+//! too: the index of a slot in the low bits, and the lap, the number of times
+//! the queue went around, in the bits above. A slot is ready to be written at
+//! position `pos` when its stamp equals `pos`, and ready to be read when it
+//! equals `pos + 1`. After reading, it is set to `pos` in the next lap, where
+//! the next write to this slot happens.
+//!
+//! A lap is a power of two larger than the capacity, so `pos + 1` never
+//! reaches the next lap, not even with a single slot.
+
+use crate::cache_padded::CachePadded;
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+struct Slot<T> {
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+pub struct ArrayQueue<T> {
+    slots: Box<[Slot<T>]>,
+    /// The stamp of the first index of a lap.
+    one_lap: usize,
+    /// The position of the next pop.
+    head: CachePadded<AtomicUsize>,
+    /// The position of the next push.
+    tail: CachePadded<AtomicUsize>,
+}
+
+unsafe impl<T> Sync for ArrayQueue<T> where T: Send {}
+
+impl<T> ArrayQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be non-zero");
+        Self {
+            slots: (0..capacity)
+                .map(|i| Slot {
+                    stamp: AtomicUsize::new(i),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            one_lap: (capacity + 1).next_power_of_two(),
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the slot that `pos` refers to.
+    fn slot(&self, pos: usize) -> &Slot<T> {
+        &self.slots[pos & (self.one_lap - 1)]
+    }
+
+    /// Returns the position after `pos`, which is the first index of the next
+    /// lap after the last index.
+    fn next(&self, pos: usize) -> usize {
+        if (pos & (self.one_lap - 1)) + 1 < self.slots.len() {
+            pos + 1
+        } else {
+            (pos & !(self.one_lap - 1)).wrapping_add(self.one_lap)
+        }
+    }
+
+    /// Adds `value` to the back of the queue, or gives it back if the queue is
+    /// full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = self.slot(pos);
+            // Acquire to synchronize with the pop that freed the slot.
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            if stamp == pos {
+                // The slot is free; try to claim the position.
+                match self.tail.compare_exchange_weak(
+                    pos,
+                    self.next(pos),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.stamp.store(pos + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(p) => pos = p,
+                }
+            } else if stamp.wrapping_add(self.one_lap) == pos + 1 {
+                // The slot still holds the value from one lap ago.
+                return Err(value);
+            } else {
+                // Another push claimed this position already.
+                pos = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Removes the value at the front of the queue, if any.
+    pub fn pop(&self) -> Option<T> {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = self.slot(pos);
+            // Acquire to synchronize with the push that filled the slot.
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            if stamp == pos + 1 {
+                // The slot is filled; try to claim the position.
+                match self.head.compare_exchange_weak(
+                    pos,
+                    self.next(pos),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.stamp
+                            .store(pos.wrapping_add(self.one_lap), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(p) => pos = p,
+                }
+            } else if stamp == pos {
+                // Nothing has been pushed to this position yet.
+                return None;
+            } else {
+                // Another pop claimed this position already.
+                pos = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<T> Drop for ArrayQueue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queue::ArrayQueue;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn fifo() {
+        let q = ArrayQueue::new(3);
+        assert_eq!(q.pop(), None);
+        for lap in 0..3 {
+            q.push(lap).unwrap();
+            q.push(lap + 10).unwrap();
+            q.push(lap + 20).unwrap();
+            assert_eq!(q.push(99), Err(99));
+            assert_eq!(q.pop(), Some(lap));
+            assert_eq!(q.pop(), Some(lap + 10));
+            assert_eq!(q.pop(), Some(lap + 20));
+            assert_eq!(q.pop(), None);
+        }
+    }
+
+    #[test]
+    fn capacity_one() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop(i32);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let q = ArrayQueue::new(1);
+        for lap in 0..3 {
+            assert!(q.push(DetectDrop(lap)).is_ok());
+            let rejected = q.push(DetectDrop(99)).unwrap_err();
+            assert_eq!(rejected.0, 99);
+            drop(rejected);
+            assert_eq!(q.pop().unwrap().0, lap);
+            assert!(q.pop().is_none());
+        }
+        assert!(q.push(DetectDrop(3)).is_ok());
+        drop(q);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 7);
+    }
+
+    #[test]
+    fn drop_remaining() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let q = ArrayQueue::new(4);
+        for _ in 0..3 {
+            assert!(q.push(DetectDrop).is_ok());
+        }
+        drop(q.pop());
+        drop(q);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test() {
+        const PER_THREAD: usize = 10_000;
+        let q = ArrayQueue::new(16);
+        let seen: Vec<AtomicUsize> = (0..4 * PER_THREAD).map(|_| AtomicUsize::new(0)).collect();
+        let popped = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for t in 0..4 {
+                let q = &q;
+                s.spawn(move || {
+                    for i in t * PER_THREAD..(t + 1) * PER_THREAD {
+                        let mut value = i;
+                        while let Err(v) = q.push(value) {
+                            value = v;
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while popped.load(Ordering::Relaxed) < 4 * PER_THREAD {
+                        match q.pop() {
+                            Some(i) => {
+                                seen[i].fetch_add(1, Ordering::Relaxed);
+                                popped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                });
+            }
+        });
+        assert!(seen.iter().all(|n| n.load(Ordering::Relaxed) == 1));
+    }
+}
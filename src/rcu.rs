@@ -0,0 +1,134 @@
+//! Read-copy-update: read-mostly state that readers take snapshots of, and
+//! writers replace with updated copies instead of changing it in place.
+
+use crate::arc::Arc;
+use crate::arc_swap::ArcSwap;
+
+/// A value that readers can take a snapshot of without taking a lock, even
+/// while it's being updated.
+///
+/// A read only waits if it has to allocate a new hazard slot, which happens
+/// when more threads are reading at once than ever before.
+///
+/// Each update installs a new version, and a version is freed once the cell
+/// and the last snapshot of it are gone.
+pub struct Rcu<T> {
+    current: ArcSwap<T>,
+}
+
+impl<T> Rcu<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            current: ArcSwap::new(Arc::new(value)),
+        }
+    }
+
+    /// Returns the current version, which stays the same for as long as it
+    /// is kept, regardless of updates.
+    pub fn read(&self) -> Arc<T> {
+        self.current.load()
+    }
+
+    /// Installs `f(&current)` as the new version.
+    ///
+    /// If another update is installed in the meantime, the result is thrown
+    /// away, and `f` is called again on the newer version, so no update is
+    /// ever lost. That's why `f` is `FnMut` rather than `FnOnce`.
+    pub fn update(&self, mut f: impl FnMut(&T) -> T) {
+        let mut current = self.current.load();
+        loop {
+            let new = Arc::new(f(&current));
+            match self.current.compare_and_swap(&current, new) {
+                Ok(()) => return,
+                Err(_) => current = self.current.load(),
+            }
+        }
+    }
+}
+
+impl<T: Default> Default for Rcu<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for Rcu<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rcu::Rcu;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test() {
+        static NUM_CREATED: AtomicUsize = AtomicUsize::new(0);
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        // Two fields that each update changes together, so a reader would
+        // notice a half-updated version.
+        struct Version {
+            number: usize,
+            double: usize,
+        }
+        impl Drop for Version {
+            fn drop(&mut self) {
+                assert_eq!(self.double, self.number * 2);
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let rcu = Rcu::new(Version {
+            number: 0,
+            double: 0,
+        });
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..10_000 {
+                        let version = rcu.read();
+                        assert_eq!(version.double, version.number * 2);
+                        assert!(version.number >= last);
+                        last = version.number;
+                    }
+                });
+            }
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..1_000 {
+                        rcu.update(|v| {
+                            NUM_CREATED.fetch_add(1, Ordering::Relaxed);
+                            Version {
+                                number: v.number + 1,
+                                double: v.double + 2,
+                            }
+                        });
+                    }
+                });
+            }
+        });
+        let last = rcu.read();
+        // Neither writer's updates got lost.
+        assert_eq!(last.number, 2_000);
+        drop(rcu);
+        // Every version but the last was freed, including the first one and
+        // the ones that lost the race to be installed.
+        let num_created = NUM_CREATED.load(Ordering::Relaxed);
+        assert!(num_created >= 2_000);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), num_created);
+        drop(last);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), num_created + 1);
+    }
+
+    #[test]
+    fn snapshot_outlives_update() {
+        let rcu = Rcu::from(vec![1, 2]);
+        let old = rcu.read();
+        rcu.update(|v| v.iter().map(|x| x * 10).collect());
+        assert_eq!(*old, [1, 2]);
+        assert_eq!(*rcu.read(), [10, 20]);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::sync::thread_local;
+use std::cell::UnsafeCell;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A spin lock that can be locked again by the thread that's already holding
+/// it.
+///
+/// Since the same thread can hold several guards at once, the guards only give
+/// shared access to the value. Use a `Cell` or `RefCell` for mutation.
+pub struct ReentrantSpinLock<T> {
+    /// The id of the owning thread, or 0 when unlocked.
+    owner: AtomicU64,
+    /// How many guards the owning thread holds. Only accessed by the owner.
+    count: UnsafeCell<usize>,
+    value: T,
+}
+
+unsafe impl<T> Sync for ReentrantSpinLock<T> where T: Send {}
+
+/// Returns a unique non-zero id for the current thread.
+///
+/// Unlike the address of a thread local, this is never reused by a later
+/// thread, so a lock that was leaked by an exited thread stays locked. With
+/// loom, every modelled thread gets its own id.
+pub(crate) fn current_thread_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static ID: u64 = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    }
+    ID.with(|id| *id)
+}
+
+impl<T> ReentrantSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            owner: AtomicU64::new(0),
+            count: UnsafeCell::new(0),
+            value,
+        }
+    }
+
+    pub fn lock(&self) -> Guard<'_, T> {
+        let id = current_thread_id();
+        // Only we can have stored our own id, so Relaxed is enough to see it.
+        if self.owner.load(Ordering::Relaxed) != id {
+            while self
+                .owner
+                .compare_exchange_weak(0, id, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                std::hint::spin_loop();
+            }
+        }
+        // Safety: We own the lock, so nobody else touches the count.
+        let count = unsafe { &mut *self.count.get() };
+        *count = count.checked_add(1).expect("lock count overflow");
+        Guard {
+            lock: self,
+            _no_send: PhantomData,
+        }
+    }
+}
+
+pub struct Guard<'a, T> {
+    lock: &'a ReentrantSpinLock<T>,
+    /// The guard must be dropped on the thread that owns the lock.
+    _no_send: PhantomData<*const ()>,
+}
+
+impl<T> Deref for Guard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.lock.value
+    }
+}
+
+impl<T> Drop for Guard<'_, T> {
+    fn drop(&mut self) {
+        // Safety: The guard's existence means this thread owns the lock.
+        let count = unsafe { &mut *self.lock.count.get() };
+        *count -= 1;
+        if *count == 0 {
+            self.lock.owner.store(0, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "loom")))]
+mod tests {
+    use crate::reentrant_spin_lock::ReentrantSpinLock;
+    use std::cell::Cell;
+    use std::thread;
+
+    #[test]
+    fn nested() {
+        let lock = ReentrantSpinLock::new(Cell::new(0));
+        let a = lock.lock();
+        let b = lock.lock();
+        a.set(1);
+        assert_eq!(b.get(), 1);
+        drop(a);
+        // Still locked by `b`.
+        thread::scope(|s| {
+            let other = s.spawn(|| lock.lock().get());
+            thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!other.is_finished());
+            b.set(2);
+            drop(b);
+            assert_eq!(other.join().unwrap(), 2);
+        });
+    }
+
+    #[test]
+    fn test() {
+        let lock = ReentrantSpinLock::new(Cell::new(0));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        let outer = lock.lock();
+                        let inner = lock.lock();
+                        inner.set(outer.get() + 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(lock.lock().get(), 40_000);
+    }
+}
@@ -0,0 +1,100 @@
+//! A list of observers that doesn't keep them alive, for publishing events to
+//! whoever is still interested.
+
+use crate::arc::{Arc, Weak};
+use crate::spin_lock::SpinLock;
+use ::alloc::vec::Vec;
+
+pub struct WeakRegistry<T> {
+    observers: SpinLock<Vec<Weak<T>>>,
+}
+
+impl<T> WeakRegistry<T> {
+    pub const fn new() -> Self {
+        Self {
+            observers: SpinLock::new(Vec::new()),
+        }
+    }
+
+    /// Adds an observer, which stays registered until its last `Arc` is gone.
+    pub fn register(&self, arc: &Arc<T>) {
+        let weak = Arc::downgrade(arc);
+        self.observers.lock().push(weak);
+    }
+
+    /// Calls `f` on every observer that is still alive, and removes the ones
+    /// that aren't.
+    ///
+    /// Every observer is upgraded once, in a single pass under the lock, which
+    /// also removes those that fail to upgrade. `f` is only called after the
+    /// lock is released, so it may register more observers, which it won't
+    /// see this time.
+    pub fn for_each_live(&self, f: impl FnMut(Arc<T>)) {
+        let mut live = Vec::new();
+        self.observers.lock().retain(|weak| match weak.upgrade() {
+            Some(arc) => {
+                live.push(arc);
+                true
+            }
+            None => false,
+        });
+        live.into_iter().for_each(f);
+    }
+
+    /// Removes the observers that are gone, and returns the number of those
+    /// that are left, some of which may be gone right after.
+    pub fn len_live(&self) -> usize {
+        let mut observers = self.observers.lock();
+        observers.retain(|weak| weak.strong_count() > 0);
+        observers.len()
+    }
+}
+
+impl<T> Default for WeakRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arc::Arc;
+    use crate::registry::WeakRegistry;
+
+    #[test]
+    fn test() {
+        let registry = WeakRegistry::new();
+        let mut observers: Vec<_> = (0..5).map(Arc::new).collect();
+        for observer in &observers {
+            registry.register(observer);
+        }
+        assert_eq!(registry.len_live(), 5);
+        drop(observers.remove(3));
+        drop(observers.remove(1));
+
+        let mut seen = Vec::new();
+        registry.for_each_live(|observer| seen.push(*observer));
+        assert_eq!(seen, [0, 2, 4]);
+        // The dead ones were pruned along the way.
+        assert_eq!(registry.observers.lock().len(), 3);
+
+        drop(observers.pop());
+        assert_eq!(registry.len_live(), 2);
+        assert_eq!(registry.observers.lock().len(), 2);
+    }
+
+    #[test]
+    fn register_from_callback() {
+        let registry = WeakRegistry::new();
+        let a = Arc::new(1);
+        let b = Arc::new(2);
+        registry.register(&a);
+        let mut calls = 0;
+        registry.for_each_live(|_| {
+            calls += 1;
+            registry.register(&b);
+        });
+        assert_eq!(calls, 1);
+        assert_eq!(registry.len_live(), 2);
+    }
+}
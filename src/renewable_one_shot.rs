@@ -0,0 +1,275 @@
+//! A one-shot channel that can be renewed for another round once a message has
+//! been handed over, reusing the same allocation.
+//!
+//! Every round has its own generation. The halves remember the generation they
+//! were created for, and are stale once the channel has moved on to the next
+//! round, after which they can't send or receive anymore.
+
+use crate::arc::Arc;
+use crate::futex::{wait, wake_all};
+use crate::one_shot::RecvError;
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Set once a sender has started writing the round's message.
+const CLAIMED: u32 = 1;
+/// Set once the round's message has been written.
+const READY: u32 = 2;
+/// Set once the round is over: the message was received or discarded, or the
+/// sender was dropped without sending.
+const DONE: u32 = 4;
+const FLAGS: u32 = CLAIMED | READY | DONE;
+/// The generation is stored above the flags.
+const GENERATION_SHIFT: u32 = 3;
+
+struct Shared<T> {
+    message: UnsafeCell<MaybeUninit<T>>,
+    /// The current generation, shifted by `GENERATION_SHIFT`, plus the flags of
+    /// the current round.
+    state: AtomicU32,
+}
+
+unsafe impl<T> Sync for Shared<T> where T: Send {}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    generation: u32,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    generation: u32,
+}
+
+/// Creates a channel for the first round.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        message: UnsafeCell::new(MaybeUninit::uninit()),
+        state: AtomicU32::new(0),
+    });
+    pair(shared, 0)
+}
+
+fn pair<T>(shared: Arc<Shared<T>>, generation: u32) -> (Sender<T>, Receiver<T>) {
+    (
+        Sender {
+            shared: shared.clone(),
+            generation,
+        },
+        Receiver { shared, generation },
+    )
+}
+
+fn generation_of(state: u32) -> u32 {
+    state >> GENERATION_SHIFT
+}
+
+/// Starts the round after `generation`, if that round is over.
+fn renew<T>(shared: &Arc<Shared<T>>, generation: u32) -> Option<(Sender<T>, Receiver<T>)> {
+    let state = shared.state.load(Ordering::Relaxed);
+    if generation_of(state) != generation || state & DONE == 0 {
+        return None;
+    }
+    // The generation wraps around, after which a half that has been stale
+    // for that many rounds would be mistaken for a current one.
+    let next = (state & !FLAGS).wrapping_add(1 << GENERATION_SHIFT);
+    // AcqRel: Acquire so the next round's message is written after the
+    // last one was read, and Release to pass that on to the next sender.
+    shared
+        .state
+        .compare_exchange(state, next, Ordering::AcqRel, Ordering::Relaxed)
+        .ok()?;
+    Some(pair(shared.clone(), generation_of(next)))
+}
+
+impl<T> Sender<T> {
+    /// Sends the message for this sender's round.
+    ///
+    /// Gives the message back if the round already got one, is over, or if the
+    /// sender is stale.
+    pub fn send(&self, message: T) -> Result<(), T> {
+        let state = self.generation << GENERATION_SHIFT;
+        if self
+            .shared
+            .state
+            .compare_exchange(state, state | CLAIMED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(message);
+        }
+        // Safety: We've claimed the round, so nobody else touches the message
+        // until we set the ready flag.
+        unsafe { (*self.shared.message.get()).write(message) };
+        self.shared.state.fetch_or(READY, Ordering::Release);
+        wake_all(&self.shared.state);
+        Ok(())
+    }
+
+    /// Starts the next round, once this one is over, and returns a new pair of
+    /// halves for it. Returns `None` if the round isn't over yet, or if it has
+    /// already been renewed.
+    pub fn renew(&self) -> Option<(Sender<T>, Receiver<T>)> {
+        renew(&self.shared, self.generation)
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // If we haven't sent anything, the round ends without a message.
+        let state = self.generation << GENERATION_SHIFT;
+        if self
+            .shared
+            .state
+            .compare_exchange(state, state | DONE, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            wake_all(&self.shared.state);
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Blocks until the message of this receiver's round arrives.
+    ///
+    /// Returns an error if the sender was dropped without sending, the message
+    /// was already received, or the receiver is stale. Takes `&mut self`, so
+    /// that only one thread at a time can be taking the message out.
+    pub fn receive(&mut self) -> Result<T, RecvError> {
+        loop {
+            let state = self.shared.state.load(Ordering::Acquire);
+            if generation_of(state) != self.generation || state & DONE != 0 {
+                return Err(RecvError);
+            }
+            if state & READY != 0 {
+                // Safety: The message is ready, and only we can end the round
+                // now, which we do only after moving the message out.
+                let message = unsafe { (*self.shared.message.get()).assume_init_read() };
+                // Release, so the round can only be renewed after the read.
+                self.shared.state.fetch_or(DONE, Ordering::Release);
+                return Ok(message);
+            }
+            wait(&self.shared.state, state);
+        }
+    }
+
+    /// Starts the next round, once this one is over, and returns a new pair of
+    /// halves for it. Returns `None` if the round isn't over yet, or if it has
+    /// already been renewed.
+    pub fn renew(&self) -> Option<(Sender<T>, Receiver<T>)> {
+        renew(&self.shared, self.generation)
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        // End the round, dropping a message that was sent but never received,
+        // so that senders can't send into the void anymore.
+        loop {
+            let state = self.shared.state.load(Ordering::Acquire);
+            if generation_of(state) != self.generation || state & DONE != 0 {
+                return;
+            }
+            if state & READY != 0 {
+                // Safety: Same as in `receive`.
+                unsafe { (*self.shared.message.get()).assume_init_drop() };
+                self.shared.state.fetch_or(DONE, Ordering::Release);
+                return;
+            }
+            if state & CLAIMED != 0 {
+                // A sender is writing the message right now.
+                wait(&self.shared.state, state);
+                continue;
+            }
+            if self
+                .shared
+                .state
+                .compare_exchange(state, state | DONE, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let state = *self.state.get_mut();
+        if state & READY != 0 && state & DONE == 0 {
+            // Safety: The message was sent, but never received.
+            unsafe { self.message.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::one_shot::RecvError;
+    use crate::renewable_one_shot::channel;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn ping_pong() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop(usize);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let (mut sender, mut receiver) = channel();
+        for i in 0..100 {
+            let received = thread::scope(|s| {
+                s.spawn(|| assert!(sender.send(DetectDrop(i)).is_ok()));
+                receiver.receive().unwrap()
+            });
+            assert_eq!(received.0, i);
+            drop(received);
+            // Alternate which half starts the next round.
+            let next = if i % 2 == 0 {
+                sender.renew()
+            } else {
+                receiver.renew()
+            };
+            (sender, receiver) = next.unwrap();
+        }
+        drop((sender, receiver));
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 100);
+    }
+
+    #[test]
+    fn stale_generation() {
+        let (sender, mut receiver) = channel();
+        // The round isn't over yet.
+        assert!(receiver.renew().is_none());
+        assert!(sender.send(1).is_ok());
+        assert_eq!(sender.send(2), Err(2));
+        assert_eq!(receiver.receive(), Ok(1));
+        assert_eq!(receiver.receive(), Err(RecvError));
+        let (new_sender, mut new_receiver) = sender.renew().unwrap();
+        // Only one new pair per round.
+        assert!(receiver.renew().is_none());
+        // The old halves can't interfere with the new round.
+        assert_eq!(sender.send(3), Err(3));
+        drop(sender);
+        drop(receiver);
+        assert!(new_sender.send(4).is_ok());
+        assert_eq!(new_receiver.receive(), Ok(4));
+    }
+
+    #[test]
+    fn sender_dropped() {
+        let (sender, mut receiver) = channel::<i32>();
+        thread::scope(|s| {
+            s.spawn(move || drop(sender));
+            assert_eq!(receiver.receive(), Err(RecvError));
+        });
+        let (sender, receiver) = receiver.renew().unwrap();
+        // An unreceived message is dropped along with the receiver.
+        assert!(sender.send(5).is_ok());
+        drop(receiver);
+        assert!(sender.renew().is_some());
+    }
+}
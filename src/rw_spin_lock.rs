@@ -0,0 +1,359 @@
+use std::cell::UnsafeCell;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The `state` of a write-locked lock.
+const WRITE_LOCKED: u32 = u32::MAX;
+
+/// The bit of `state` that is set while an upgradable read lock is held.
+const UPGRADABLE: u32 = 1 << 31;
+
+/// The bit of `state` that a waiting writer sets to keep new readers out, if
+/// the lock prefers writers.
+const WRITER_WAITING: u32 = 1 << 30;
+
+/// The maximum number of plain readers, which keeps a read-locked `state`
+/// from ever looking like `WRITE_LOCKED`.
+const MAX_READERS: u32 = WRITER_WAITING - 2;
+
+/// A spin lock that allows either any number of readers, or a single writer.
+///
+/// One of the readers may hold an upgradable read lock, which it can turn into
+/// a write lock without letting another writer in first.
+///
+/// By default, readers can keep getting in while a writer is waiting, so a
+/// steady stream of readers can starve writers. A lock created with
+/// [`with_writer_preference`](Self::with_writer_preference) keeps new readers
+/// out while a writer is waiting instead. Readers that already got in can
+/// still leave, but a thread that takes a second read lock while holding one
+/// will deadlock if a writer starts waiting in between.
+pub struct RwSpinLock<T> {
+    /// The number of plain readers, plus `UPGRADABLE` if there is an
+    /// upgradable reader, plus `WRITER_WAITING` if a writer is waiting, or
+    /// `WRITE_LOCKED` if write-locked.
+    state: AtomicU32,
+    prefer_writers: bool,
+    value: UnsafeCell<T>,
+}
+
+/// Readers share `&T` across threads, so `T` must be `Sync` as well.
+unsafe impl<T> Sync for RwSpinLock<T> where T: Send + Sync {}
+
+impl<T> RwSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            prefer_writers: false,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Creates a lock that doesn't let new readers in while a writer is
+    /// waiting.
+    pub const fn with_writer_preference(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            prefer_writers: true,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        let mut n = self.state.load(Ordering::Relaxed);
+        loop {
+            // This is also false while write-locked or a writer is waiting.
+            if n & !UPGRADABLE < MAX_READERS {
+                match self.state.compare_exchange_weak(
+                    n,
+                    n + 1,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return ReadGuard { lock: self },
+                    Err(e) => n = e,
+                }
+            } else {
+                std::hint::spin_loop();
+                n = self.state.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Takes a read lock that can later be upgraded to a write lock. Plain
+    /// readers can come and go, but only one upgradable reader can exist at
+    /// a time.
+    pub fn upgradable_read(&self) -> UpgradableReadGuard<'_, T> {
+        let mut n = self.state.load(Ordering::Relaxed);
+        loop {
+            // This is also false while write-locked or a writer is waiting.
+            if n & (UPGRADABLE | WRITER_WAITING) == 0 {
+                match self.state.compare_exchange_weak(
+                    n,
+                    n | UPGRADABLE,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return UpgradableReadGuard { lock: self },
+                    Err(e) => n = e,
+                }
+            } else {
+                std::hint::spin_loop();
+                n = self.state.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn write(&self) -> WriteGuard<'_, T> {
+        let mut n = self.state.load(Ordering::Relaxed);
+        loop {
+            // Our own (or another writer's) waiting bit doesn't keep us out.
+            if n & !WRITER_WAITING == 0 {
+                match self.state.compare_exchange_weak(
+                    n,
+                    WRITE_LOCKED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return WriteGuard { lock: self },
+                    Err(e) => n = e,
+                }
+                continue;
+            }
+            if self.prefer_writers && n & WRITER_WAITING == 0 {
+                // Once readers see this bit, only the ones already in can
+                // hold us up. (Setting it on `WRITE_LOCKED` changes nothing.)
+                self.state.fetch_or(WRITER_WAITING, Ordering::Relaxed);
+            }
+            std::hint::spin_loop();
+            n = self.state.load(Ordering::Relaxed);
+        }
+    }
+}
+
+pub struct ReadGuard<'a, T> {
+    lock: &'a RwSpinLock<T>,
+}
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: Nobody can write while we're holding a read lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+pub struct UpgradableReadGuard<'a, T> {
+    lock: &'a RwSpinLock<T>,
+}
+
+impl<'a, T> UpgradableReadGuard<'a, T> {
+    /// Turns the upgradable read lock into a write lock, waiting for the plain
+    /// readers to leave first.
+    ///
+    /// Writers can't get in while we wait, as the lock isn't free until the
+    /// upgradable read lock is released. New plain readers can, though, unless
+    /// a writer is waiting on a lock that prefers writers.
+    pub fn upgrade(guard: Self) -> WriteGuard<'a, T> {
+        let lock = guard.lock;
+        mem::forget(guard);
+        let mut n = lock.state.load(Ordering::Relaxed);
+        loop {
+            // A waiting writer will just have to keep waiting.
+            if n & !WRITER_WAITING == UPGRADABLE {
+                match lock.state.compare_exchange_weak(
+                    n,
+                    WRITE_LOCKED,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return WriteGuard { lock },
+                    Err(e) => n = e,
+                }
+                continue;
+            }
+            std::hint::spin_loop();
+            n = lock.state.load(Ordering::Relaxed);
+        }
+    }
+}
+
+impl<T> Deref for UpgradableReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: Nobody can write while we're holding a read lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for UpgradableReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(UPGRADABLE, Ordering::Release);
+    }
+}
+
+pub struct WriteGuard<'a, T> {
+    lock: &'a RwSpinLock<T>,
+}
+
+impl<'a, T> WriteGuard<'a, T> {
+    /// Turns the write lock into a read lock, without letting another writer
+    /// in between.
+    pub fn downgrade(guard: Self) -> ReadGuard<'a, T> {
+        let lock = guard.lock;
+        mem::forget(guard);
+        // Going straight from write-locked to a single reader. Release, so
+        // other readers that get in now see our writes.
+        lock.state.store(1, Ordering::Release);
+        ReadGuard { lock }
+    }
+}
+
+impl<T> Deref for WriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rw_spin_lock::{RwSpinLock, UpgradableReadGuard, WriteGuard, WRITER_WAITING};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let lock = RwSpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        *lock.write() += 1;
+                        let a = lock.read();
+                        let b = lock.read();
+                        assert_eq!(*a, *b);
+                    }
+                });
+            }
+        });
+        assert_eq!(*lock.read(), 40_000);
+    }
+
+    #[test]
+    fn downgrade() {
+        let lock = RwSpinLock::new(vec![1]);
+        let mut w = lock.write();
+        w.push(2);
+        let r = WriteGuard::downgrade(w);
+        assert_eq!(*r, [1, 2]);
+        thread::scope(|s| {
+            // Another reader gets in while we're still reading.
+            s.spawn(|| assert_eq!(*lock.read(), [1, 2]));
+        });
+        drop(r);
+        lock.write().push(3);
+        assert_eq!(*lock.read(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn upgradable_read() {
+        let lock = RwSpinLock::new(vec![1]);
+        let u = lock.upgradable_read();
+        assert_eq!(*u, [1]);
+        thread::scope(|s| {
+            // Plain readers can still get in, but writers and other upgradable
+            // readers can't.
+            s.spawn(|| assert_eq!(*lock.read(), [1]));
+            let writer = s.spawn(|| lock.write().push(3));
+            let other = s.spawn(|| drop(lock.upgradable_read()));
+            thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!writer.is_finished());
+            assert!(!other.is_finished());
+            let mut w = UpgradableReadGuard::upgrade(u);
+            w.push(2);
+            drop(w);
+            writer.join().unwrap();
+            other.join().unwrap();
+        });
+        assert_eq!(*lock.read(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn writer_preference() {
+        let lock = RwSpinLock::with_writer_preference(0);
+        let r = lock.read();
+        thread::scope(|s| {
+            let writer = s.spawn(|| *lock.write() += 1);
+            while lock.state.load(Ordering::Relaxed) & WRITER_WAITING == 0 {
+                thread::yield_now();
+            }
+            // New readers have to wait for the writer, but the one that is
+            // already in can leave.
+            let reader = s.spawn(|| *lock.read());
+            thread::sleep(Duration::from_millis(10));
+            assert!(!writer.is_finished());
+            assert!(!reader.is_finished());
+            drop(r);
+            writer.join().unwrap();
+            assert_eq!(reader.join().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn writer_not_starved() {
+        let lock = RwSpinLock::with_writer_preference(0);
+        let reads = AtomicUsize::new(0);
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let _r = lock.read();
+                        reads.fetch_add(1, Ordering::Relaxed);
+                        thread::sleep(Duration::from_micros(100));
+                    }
+                });
+            }
+            // Wait for a steady reader load.
+            while reads.load(Ordering::Relaxed) < 100 {
+                thread::yield_now();
+            }
+            for _ in 0..10 {
+                let before = reads.load(Ordering::Relaxed);
+                *lock.write() += 1;
+                // At most the readers that were already in when the writer
+                // started waiting, plus some that got in before that.
+                assert!(reads.load(Ordering::Relaxed) - before < 20);
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(*lock.read(), 10);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::futex::{wait_if, wake_one};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Limits how many threads can hold a permit at the same time.
+pub struct Semaphore {
+    permits: AtomicUsize,
+    /// The number of threads sleeping until a permit is given back, so that
+    /// giving one back can skip waking them up while there are none.
+    num_waiters: AtomicUsize,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+            num_waiters: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of permits that are currently available.
+    ///
+    /// This is only a snapshot, which may already be outdated when it returns.
+    pub fn available_permits(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    /// Takes a permit, blocking until one is available.
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        loop {
+            if let Some(permit) = self.try_acquire() {
+                return permit;
+            }
+            // SeqCst, like giving a permit back: either that sees us waiting,
+            // or we see its permit and don't go to sleep.
+            self.num_waiters.fetch_add(1, Ordering::SeqCst);
+            wait_if(&self.permits, || self.permits.load(Ordering::SeqCst) == 0);
+            self.num_waiters.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Takes a permit if one is available, without blocking.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let mut n = self.permits.load(Ordering::Relaxed);
+        loop {
+            if n == 0 {
+                return None;
+            }
+            // Acquire synchronizes with the Release increment of the permit's
+            // previous holder.
+            match self
+                .permits
+                .compare_exchange_weak(n, n - 1, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return Some(SemaphorePermit { semaphore: self }),
+                Err(e) => n = e,
+            }
+        }
+    }
+}
+
+/// A permit, which is given back to the semaphore when dropped.
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        // SeqCst also releases to the next holder of the permit.
+        self.semaphore.permits.fetch_add(1, Ordering::SeqCst);
+        if self.semaphore.num_waiters.load(Ordering::SeqCst) != 0 {
+            wake_one(&self.semaphore.permits);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::semaphore::Semaphore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let semaphore = Semaphore::new(2);
+        let inside = AtomicUsize::new(0);
+        let max_inside = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10 {
+                        let _permit = semaphore.acquire();
+                        let n = inside.fetch_add(1, Ordering::Relaxed) + 1;
+                        max_inside.fetch_max(n, Ordering::Relaxed);
+                        thread::sleep(Duration::from_millis(1));
+                        inside.fetch_sub(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        assert_eq!(max_inside.load(Ordering::Relaxed), 2);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn single_permit() {
+        // Every acquire after the first has to wait for the other thread, so
+        // a lost wake-up would hang.
+        let semaphore = Semaphore::new(1);
+        let count = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        let _permit = semaphore.acquire();
+                        count.fetch_add(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+        assert_eq!(count.load(Ordering::Relaxed), 20_000);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+
+    #[test]
+    fn try_acquire() {
+        let semaphore = Semaphore::new(1);
+        let permit = semaphore.try_acquire().unwrap();
+        assert!(semaphore.try_acquire().is_none());
+        drop(permit);
+        assert!(semaphore.try_acquire().is_some());
+    }
+}
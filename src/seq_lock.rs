@@ -0,0 +1,101 @@
+use std::cell::UnsafeCell;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+
+/// A sequence lock: readers never block and never write to shared memory,
+/// which makes reads very cheap when writes are rare.
+///
+/// Readers copy the value optimistically and retry if a write happened in
+/// the meantime. That's why the value must be `Copy`: a reader might copy a
+/// half-written value, which is then thrown away without ever being used.
+pub struct SeqLock<T> {
+    /// Even while unlocked, odd while a writer is writing.
+    seq: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for SeqLock<T> where T: Copy + Send {}
+
+impl<T: Copy> SeqLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn read(&self) -> T {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 1 {
+                // A writer is busy.
+                std::hint::spin_loop();
+                continue;
+            }
+            // Safety: This might race with a writer, in which case the copy is
+            // discarded below. A volatile read keeps the compiler from making
+            // any assumptions about the value.
+            let value = unsafe { ptr::read_volatile(self.value.get()) };
+            // Keeps the read of the value from being reordered after the second
+            // load of the counter.
+            fence(Ordering::Acquire);
+            if self.seq.load(Ordering::Relaxed) == seq {
+                return value;
+            }
+        }
+    }
+
+    pub fn write(&self, value: T) {
+        // Making the counter odd doubles as a lock between writers.
+        let mut seq = self.seq.load(Ordering::Relaxed);
+        loop {
+            if seq & 1 == 1 {
+                std::hint::spin_loop();
+                seq = self.seq.load(Ordering::Relaxed);
+                continue;
+            }
+            match self
+                .seq
+                .compare_exchange_weak(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(e) => seq = e,
+            }
+        }
+        // Makes sure a reader that sees any part of the new value also sees
+        // the odd counter.
+        fence(Ordering::Release);
+        // Safety: We hold the writer lock, so no other thread writes.
+        unsafe { ptr::write_volatile(self.value.get(), value) };
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::seq_lock::SeqLock;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test() {
+        let lock = SeqLock::new([0u64; 4]);
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let value = lock.read();
+                        // A torn read would mix elements of different writes.
+                        assert!(value.iter().all(|&x| x == value[0]));
+                    }
+                });
+            }
+            for i in 1..=100_000 {
+                lock.write([i; 4]);
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(lock.read(), [100_000; 4]);
+    }
+}
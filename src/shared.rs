@@ -0,0 +1,95 @@
+//! A value that is shared between threads and mutated by all of them, which
+//! is what `Arc<SpinLock<T>>` is used for most of the time.
+
+use crate::arc::Arc;
+use crate::spin_lock::{ArcGuard, SpinLock};
+
+/// A handle to a value behind a lock, shared with all its clones.
+///
+/// Guards keep their own reference to the lock, so they can outlive the
+/// handle they came from, and a guard that is still alive keeps
+/// [`try_unwrap_inner`](Self::try_unwrap_inner) from taking the value.
+pub struct SharedMut<T> {
+    lock: Arc<SpinLock<T>>,
+}
+
+impl<T> SharedMut<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            lock: Arc::new(SpinLock::new(value)),
+        }
+    }
+
+    /// Spins until the value is free, and locks it.
+    pub fn lock(&self) -> ArcGuard<T> {
+        SpinLock::lock_arc(&self.lock)
+    }
+
+    /// Returns the value if this is the only handle, and no guard is alive,
+    /// without taking the lock. Otherwise gives the handle back.
+    pub fn try_unwrap_inner(self) -> Result<T, Self> {
+        match Arc::try_unwrap(self.lock) {
+            Ok(lock) => Ok(lock.into_inner()),
+            Err(lock) => Err(Self { lock }),
+        }
+    }
+}
+
+/// Shares the value with the new handle, without cloning it.
+impl<T> Clone for SharedMut<T> {
+    fn clone(&self) -> Self {
+        Self {
+            lock: self.lock.clone(),
+        }
+    }
+}
+
+impl<T: Default> Default for SharedMut<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for SharedMut<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shared::SharedMut;
+    use std::thread;
+
+    #[test]
+    fn concurrent_mutation() {
+        let shared = SharedMut::new(Vec::new());
+        thread::scope(|s| {
+            for i in 0..4 {
+                let shared = shared.clone();
+                s.spawn(move || {
+                    for j in 0..100 {
+                        shared.lock().push(i * 100 + j);
+                    }
+                });
+            }
+        });
+        let mut values = shared.try_unwrap_inner().ok().unwrap();
+        values.sort();
+        assert!(values.into_iter().eq(0..400));
+    }
+
+    #[test]
+    fn try_unwrap_inner() {
+        let a = SharedMut::new(1);
+        let b = a.clone();
+        let a = a.try_unwrap_inner().err().unwrap();
+        drop(b);
+        // A guard that is still alive holds on to the value too.
+        let mut guard = a.lock();
+        let a = a.try_unwrap_inner().err().unwrap();
+        *guard += 1;
+        drop(guard);
+        assert_eq!(a.try_unwrap_inner().ok(), Some(2));
+    }
+}
@@ -1,42 +1,611 @@
-use std::cell::UnsafeCell;
-use std::ops::{Deref, DerefMut};
-use std::sync::atomic::{AtomicBool, Ordering};
+use crate::arc::Arc;
+#[cfg(feature = "std")]
+use crate::futex::{wait_if_until, wake_one};
+#[cfg(all(debug_assertions, feature = "std"))]
+use crate::reentrant_spin_lock::current_thread_id;
+#[cfg(feature = "std")]
+use crate::sync::atomic::AtomicU32;
+#[cfg(any(feature = "metrics", all(debug_assertions, feature = "std")))]
+use crate::sync::atomic::AtomicU64;
+use crate::sync::atomic::{AtomicBool, Ordering};
+use crate::sync::{self, const_fn, hint};
+use ::alloc::alloc::{self, Layout};
+use ::alloc::boxed::Box;
+use core::cell::UnsafeCell;
+use core::error::Error;
+use core::fmt;
+use core::mem;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+#[cfg(feature = "std")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
-pub struct SpinLock<T> {
+/// `repr(C)` keeps the value last, so that the layout of an unsized `SpinLock`
+/// can be computed by hand in [`SpinLock::from_box`].
+#[repr(C)]
+pub struct SpinLock<T: ?Sized> {
     locked: AtomicBool,
+    /// The number of threads that gave up spinning and went to sleep, which
+    /// needs `std`.
+    #[cfg(feature = "std")]
+    sleepers: AtomicU32,
+    /// The id of the thread holding the lock, or 0 when unlocked. Only kept in
+    /// debug builds, to detect a thread locking the lock it already holds, and
+    /// only with `std`, which is needed to tell threads apart.
+    #[cfg(all(debug_assertions, feature = "std"))]
+    owner: AtomicU64,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
     value: UnsafeCell<T>,
 }
 
+/// Counts how contended a lock is, with the `metrics` feature.
+#[cfg(feature = "metrics")]
+struct Metrics {
+    spins: AtomicU64,
+    acquisitions: AtomicU64,
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    fn record(&self, spins: u64) {
+        // Only add the spins once we have the lock, so spinning threads don't
+        // fight over the counter too.
+        if spins > 0 {
+            self.spins.fetch_add(spins, Ordering::Relaxed);
+        }
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// After this many failed attempts to take the lock, `lock` puts the thread
+/// to sleep, in case the thread holding the lock isn't even running. Without
+/// `std`, it keeps spinning.
+const SPIN_ROUNDS: u32 = 10;
+
+/// Between attempts, `lock` spins at most `1 << MAX_BACKOFF` times.
+const MAX_BACKOFF: u32 = 6;
+
+/// After this many failed attempts, `lock` also yields to the OS between
+/// attempts, until it goes to sleep after `SPIN_ROUNDS`. If the thread holding
+/// the lock was descheduled, for example because there are more threads than
+/// cores, spinning only delays it further, and yielding lets it run sooner.
+/// Without `std`, there's nothing to yield to.
+#[cfg(feature = "std")]
+const YIELD_ROUNDS: u32 = 4;
+
+/// How long a sleeping thread waits before checking the lock again by itself.
+///
+/// To keep unlocking as cheap as a single store, the unlocking thread checks
+/// for sleepers without synchronizing with threads going to sleep. In rare
+/// cases, it can miss one that is just going to sleep. This bounds how much
+/// such a lost wake-up can delay it.
+#[cfg(feature = "std")]
+const SLEEP_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// How long a thread waits for a lock that is held by itself, before it
+/// assumes the lock won't ever be released and panics.
+///
+/// A `Guard` may have been sent to another thread, which releases the lock
+/// eventually, so seeing our own id alone doesn't prove a recursive lock.
+#[cfg(all(debug_assertions, feature = "std"))]
+const RECURSIVE_LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
 /// Promise to the compiler that it is actually safe for our type to be shared
 /// between threads. However, since the lock can be used to send values of type
 /// T from one thread to another, we must limit this promise to types that are
 /// safe to send between threads.
-unsafe impl<T> Sync for SpinLock<T> where T: Send {}
+unsafe impl<T: ?Sized> Sync for SpinLock<T> where T: Send {}
 
 impl<T> SpinLock<T> {
-    pub fn new(value: T) -> Self {
-        Self {
-            locked: AtomicBool::new(false),
-            value: UnsafeCell::new(value),
+    const_fn! {
+        pub const fn new(value: T) -> Self {
+            Self {
+                locked: AtomicBool::new(false),
+                #[cfg(feature = "std")]
+                sleepers: AtomicU32::new(0),
+                #[cfg(all(debug_assertions, feature = "std"))]
+                owner: AtomicU64::new(0),
+                #[cfg(feature = "metrics")]
+                metrics: Metrics {
+                    spins: AtomicU64::new(0),
+                    acquisitions: AtomicU64::new(0),
+                },
+                value: UnsafeCell::new(value),
+            }
+        }
+    }
+
+    /// Returns the value. Taking the lock by value guarantees nobody holds it.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> SpinLock<T> {
+    /// Moves a boxed value into a new allocation for a boxed lock. `with_value`
+    /// turns a pointer to the allocation into a pointer to the lock, with the
+    /// metadata of the pointer to the value, e.g. the length of a slice.
+    ///
+    /// # Safety
+    ///
+    /// `with_value` must return its first argument, with the metadata of its
+    /// second argument.
+    unsafe fn move_from_box(
+        value: Box<T>,
+        with_value: impl FnOnce(*mut u8, *mut T) -> *mut Self,
+    ) -> Box<Self> {
+        let value_layout = Layout::for_value(&*value);
+        // Following `repr(C)`, the value comes right after the other fields,
+        // padded to its alignment.
+        let offset = mem::offset_of!(SpinLock<()>, value).next_multiple_of(value_layout.align());
+        let layout = Layout::from_size_align(
+            offset + value_layout.size(),
+            value_layout.align().max(mem::align_of::<SpinLock<()>>()),
+        )
+        .unwrap()
+        .pad_to_align();
+        // Safety: The layout is never zero-sized, because of the flag.
+        let alloc = unsafe { alloc::alloc(layout) };
+        if alloc.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        let value = Box::into_raw(value);
+        let lock = with_value(alloc, value);
+        unsafe {
+            ptr::addr_of_mut!((*lock).locked).write(AtomicBool::new(false));
+            #[cfg(feature = "std")]
+            ptr::addr_of_mut!((*lock).sleepers).write(AtomicU32::new(0));
+            #[cfg(all(debug_assertions, feature = "std"))]
+            ptr::addr_of_mut!((*lock).owner).write(AtomicU64::new(0));
+            #[cfg(feature = "metrics")]
+            ptr::addr_of_mut!((*lock).metrics).write(Metrics {
+                spins: AtomicU64::new(0),
+                acquisitions: AtomicU64::new(0),
+            });
+            // Safety: Moves the value into the new allocation, after which the
+            // old one is freed without dropping the value.
+            ptr::copy_nonoverlapping(value as *const u8, alloc.add(offset), value_layout.size());
+            if value_layout.size() != 0 {
+                alloc::dealloc(value as *mut u8, value_layout);
+            }
+            Box::from_raw(lock)
+        }
+    }
+}
+
+#[cfg(not(feature = "nightly"))]
+impl<T> SpinLock<[T]> {
+    /// Moves a boxed slice into a boxed lock. This is the way to create a lock
+    /// around a slice, which can't be passed to `new`. With the `nightly`
+    /// feature, this works for any unsized value.
+    pub fn from_box(value: Box<[T]>) -> Box<Self> {
+        // Safety: The new pointer has the length of the old one.
+        unsafe {
+            Self::move_from_box(value, |alloc, value| {
+                ptr::slice_from_raw_parts_mut(alloc.cast::<T>(), value.len()) as *mut Self
+            })
+        }
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl<T: ?Sized> SpinLock<T> {
+    /// Moves a boxed value into a boxed lock. This is the way to create a lock
+    /// around an unsized value, like a slice, which can't be passed to `new`.
+    pub fn from_box(value: Box<T>) -> Box<Self> {
+        // Safety: `with_metadata_of` keeps the address of the allocation.
+        unsafe {
+            Self::move_from_box(value, |alloc, value| {
+                alloc.with_metadata_of(value as *mut Self)
+            })
+        }
+    }
+}
+
+impl<T: ?Sized> SpinLock<T> {
+    /// Spins until the lock is free, and takes it. If that takes a while, the
+    /// thread starts yielding between attempts, and if it takes too long, it
+    /// goes to sleep until the lock is released instead (both with `std`).
+    ///
+    /// In debug builds with `std`, this panics if the lock stays held by the
+    /// calling thread for longer than a second while it waits, since that
+    /// thread would otherwise wait forever. Only `Guard`s count for this. A
+    /// `Guard` that was sent to another thread still counts as held by the
+    /// thread that locked, but is fine as long as it's dropped in time.
+    /// `ArcGuard`s, which are meant to be moved around, don't count.
+    pub fn lock(&self) -> Guard<'_, T> {
+        #[cfg(feature = "metrics")]
+        let mut spins = 0;
+        let mut round = 0;
+        while sync::swap(&self.locked, true, Ordering::Acquire) {
+            #[cfg(feature = "metrics")]
+            {
+                spins += 1;
+            }
+            if round < SPIN_ROUNDS {
+                round += 1;
+            } else {
+                #[cfg(feature = "std")]
+                {
+                    self.lock_contended();
+                    break;
+                }
+            }
+            // Spin twice as long after every failed attempt, so that waiting
+            // threads don't keep fighting over the cache line.
+            for _ in 0..1 << round.min(MAX_BACKOFF) {
+                // Tells the processor that we’re spinning while waiting for `locked` to change.
+                // On most major platforms, this hint results in a special instruction that
+                // causes the processor core to optimize its behavior for such a situation
+                hint::spin_loop();
+            }
+            #[cfg(feature = "std")]
+            if round > YIELD_ROUNDS {
+                sync::thread::yield_now();
+            }
         }
+        #[cfg(feature = "metrics")]
+        self.metrics.record(spins);
+        #[cfg(all(debug_assertions, feature = "std"))]
+        self.owner.store(current_thread_id(), Ordering::Relaxed);
+        Guard { lock: self }
     }
 
-    pub fn lock(&self) -> Guard<T> {
-        while self.locked.swap(true, Ordering::Acquire) {
-            // Tells the processor that we’re spinning while waiting for `locked` to change.
-            // On most major platforms, this hint results in a special instruction that
-            // causes the processor core to optimize its behavior for such a situation
-            std::hint::spin_loop();
+    /// Like `lock`, but goes to sleep right away if the lock is held, without
+    /// spinning first. For callers that know spinning won't pay off.
+    #[cfg(all(feature = "std", not(feature = "loom")))]
+    pub(crate) fn lock_without_spinning(&self) -> Guard<'_, T> {
+        if sync::swap(&self.locked, true, Ordering::Acquire) {
+            self.lock_contended();
         }
+        #[cfg(feature = "metrics")]
+        self.metrics.record(0);
+        #[cfg(debug_assertions)]
+        self.owner.store(current_thread_id(), Ordering::Relaxed);
         Guard { lock: self }
     }
+
+    /// Returns whether any threads are sleeping until the lock is released.
+    /// Only a snapshot, like `is_locked`.
+    #[cfg(all(feature = "std", not(feature = "loom")))]
+    pub(crate) fn has_sleepers(&self) -> bool {
+        self.sleepers.load(Ordering::Relaxed) != 0
+    }
+
+    /// Sleeps until the lock is released, and takes it.
+    #[cfg(feature = "std")]
+    #[cold]
+    fn lock_contended(&self) {
+        // Since when the lock has been held by this thread, as far as we know.
+        #[cfg(debug_assertions)]
+        let mut held_by_us_since = None;
+        self.sleepers.fetch_add(1, Ordering::Relaxed);
+        while sync::swap(&self.locked, true, Ordering::Acquire) {
+            #[cfg(debug_assertions)]
+            self.check_recursive(&mut held_by_us_since);
+            let deadline = Instant::now() + SLEEP_TIMEOUT;
+            wait_if_until(
+                &self.sleepers,
+                || self.locked.load(Ordering::Relaxed),
+                deadline,
+            );
+        }
+        self.sleepers.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Panics if the lock has been held by this thread for longer than
+    /// `RECURSIVE_LOCK_TIMEOUT`, while this thread is waiting for it.
+    #[cfg(all(debug_assertions, feature = "std"))]
+    fn check_recursive(&self, held_by_us_since: &mut Option<Instant>) {
+        // Only we ever store our own id, and it's reset before unlocking, so
+        // seeing it means our guard hasn't been dropped since.
+        if self.owner.load(Ordering::Relaxed) != current_thread_id() {
+            *held_by_us_since = None;
+            return;
+        }
+        let since = *held_by_us_since.get_or_insert_with(Instant::now);
+        if since.elapsed() > RECURSIVE_LOCK_TIMEOUT {
+            self.sleepers.fetch_sub(1, Ordering::Relaxed);
+            panic!("recursive lock on non-reentrant SpinLock");
+        }
+    }
+
+    /// Like `lock`, but the guard keeps its own `Arc` to the lock instead of
+    /// borrowing it, so it can be returned or moved to another thread freely.
+    pub fn lock_arc(lock: &Arc<Self>) -> ArcGuard<T> {
+        // Forget the borrowing guard; the `ArcGuard` unlocks instead.
+        core::mem::forget(lock.lock());
+        // The guard may end up on any thread, so this one no longer owns the
+        // lock.
+        #[cfg(all(debug_assertions, feature = "std"))]
+        lock.owner.store(0, Ordering::Relaxed);
+        ArcGuard { lock: lock.clone() }
+    }
+
+    /// Takes the lock if it is free, without spinning.
+    pub fn try_lock(&self) -> Result<Guard<'_, T>, TryLockError> {
+        if sync::swap(&self.locked, true, Ordering::Acquire) {
+            Err(TryLockError::WouldBlock)
+        } else {
+            #[cfg(feature = "metrics")]
+            self.metrics.record(0);
+            #[cfg(all(debug_assertions, feature = "std"))]
+            self.owner.store(current_thread_id(), Ordering::Relaxed);
+            Ok(Guard { lock: self })
+        }
+    }
+
+    /// Tries to take the lock up to `spins` times, and gives up if it is still
+    /// held after that.
+    pub fn try_lock_for(&self, spins: u32) -> Option<Guard<'_, T>> {
+        for _ in 0..spins {
+            if let Ok(guard) = self.try_lock() {
+                return Some(guard);
+            }
+            hint::spin_loop();
+        }
+        None
+    }
+
+    /// Runs `f` with the lock held, and releases it before returning `f`'s
+    /// result, even if `f` panics.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Like `with`, but if `body` panics, `on_panic` gets to repair the value
+    /// before the lock is released and the panic continues.
+    ///
+    /// This way no other thread can observe the value in the half-updated
+    /// state `body` may have left it in.
+    #[cfg(feature = "std")]
+    pub fn lock_with_recovery<R>(
+        &self,
+        body: impl FnOnce(&mut T) -> R,
+        on_panic: impl FnOnce(&mut T),
+    ) -> R {
+        let mut guard = self.lock();
+        match panic::catch_unwind(AssertUnwindSafe(|| body(&mut guard))) {
+            Ok(result) => result,
+            Err(payload) => {
+                on_panic(&mut guard);
+                drop(guard);
+                panic::resume_unwind(payload);
+            }
+        }
+    }
+
+    /// Returns the total number of times threads went around the spin loop
+    /// while waiting for this lock.
+    #[cfg(feature = "metrics")]
+    pub fn spin_count(&self) -> u64 {
+        self.metrics.spins.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of times this lock has been taken.
+    #[cfg(feature = "metrics")]
+    pub fn acquire_count(&self) -> u64 {
+        self.metrics.acquisitions.load(Ordering::Relaxed)
+    }
+
+    /// Releases the lock. Must only be called by the thread holding it.
+    fn unlock(&self) {
+        #[cfg(all(debug_assertions, feature = "std"))]
+        self.owner.store(0, Ordering::Relaxed);
+        self.locked.store(false, Ordering::Release);
+        #[cfg(feature = "std")]
+        wake_sleeper(&self.sleepers);
+    }
+
+    /// Returns whether the lock is currently held.
+    ///
+    /// This is only a snapshot: the lock may be taken or released right after
+    /// this returns. Useful for debugging, but never for synchronization.
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
 }
 
-pub struct Guard<'a, T> {
+/// Wakes up one of the threads sleeping on a lock that was just unlocked, if
+/// there are any.
+#[cfg(feature = "std")]
+fn wake_sleeper(sleepers: &AtomicU32) {
+    if sleepers.load(Ordering::Relaxed) != 0 {
+        wake_one(sleepers);
+    }
+}
+
+impl<T: Copy> SpinLock<T> {
+    /// Replaces the value with the result of `f` applied to it, under a single
+    /// lock acquisition, so no other thread can change the value in between.
+    pub fn update(&self, f: impl FnOnce(T) -> T) {
+        let mut guard = self.lock();
+        *guard = f(*guard);
+    }
+}
+
+impl<T: Default> Default for SpinLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SpinLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("SpinLock");
+        // Never wait for the lock, so printing can't deadlock.
+        match self.try_lock() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(_) => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish()
+    }
+}
+
+/// Returned by `try_lock` when the lock couldn't be taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TryLockError {
+    /// The lock is held by another thread.
+    WouldBlock,
+}
+
+impl fmt::Display for TryLockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLockError::WouldBlock => f.write_str("lock is held by another thread"),
+        }
+    }
+}
+
+impl Error for TryLockError {}
+
+/// Gives exclusive access to the value while the lock is held, and unlocks it
+/// when dropped.
+///
+/// A guard holding a value that can't be sent to another thread can't be sent
+/// either:
+///
+/// ```compile_fail
+/// use rust_atomics::spin_lock::SpinLock;
+/// fn assert_send<T: Send>(_: T) {}
+/// let lock = SpinLock::new(std::rc::Rc::new(0));
+/// assert_send(lock.lock());
+/// ```
+///
+/// And a guard can only be shared between threads if the value can be:
+///
+/// ```compile_fail
+/// use rust_atomics::spin_lock::SpinLock;
+/// fn assert_sync<T: Sync>(_: T) {}
+/// let lock = SpinLock::new(core::cell::Cell::new(0));
+/// assert_sync(lock.lock());
+/// ```
+pub struct Guard<'a, T: ?Sized> {
     lock: &'a SpinLock<T>,
 }
 
-impl<T> Deref for Guard<'_, T> {
+/// The thread that receives the guard gets `&mut T`, and unlocks the lock
+/// when it's done. Unlike some OS locks, a spin lock doesn't mind being
+/// unlocked by another thread than the one that locked it.
+unsafe impl<T: ?Sized + Send> Send for Guard<'_, T> {}
+
+/// Sharing a guard shares `&T`, so `T` must be `Sync`. Through
+/// [`Guard::lock`], it also shares the lock itself, which outlives the guard,
+/// so `T` must be `Send` as well.
+unsafe impl<T: ?Sized + Send + Sync> Sync for Guard<'_, T> {}
+
+impl<'a, T: ?Sized> Guard<'a, T> {
+    /// Turns the guard into one that only gives shared access to the part of
+    /// the value selected by `f`. The lock stays held until the new guard is
+    /// dropped.
+    pub fn map_ref<U: ?Sized, F: FnOnce(&T) -> &U>(guard: Self, f: F) -> MappedRefGuard<'a, U> {
+        let lock = guard.lock;
+        // Safety: The lock stays locked for 'a, or until the mapped guard is
+        // dropped, whichever comes first.
+        let value = f(unsafe { &*lock.value.get() });
+        // Forget the guard only now, so the lock is released if `f` panics.
+        core::mem::forget(guard);
+        MappedRefGuard {
+            locked: &lock.locked,
+            #[cfg(feature = "std")]
+            sleepers: &lock.sleepers,
+            #[cfg(all(debug_assertions, feature = "std"))]
+            owner: &lock.owner,
+            value,
+        }
+    }
+
+    /// Returns the lock this guard belongs to.
+    pub fn lock(guard: &Self) -> &'a SpinLock<T> {
+        guard.lock
+    }
+
+    /// Briefly releases the lock to give waiting threads a chance to take it,
+    /// and takes it back before returning.
+    ///
+    /// Other threads may have changed the value in the meantime, so anything
+    /// read through the guard before the bump may be outdated afterwards.
+    pub fn bump(guard: &mut Self) {
+        guard.lock.unlock();
+        #[cfg(feature = "std")]
+        crate::sync::thread::yield_now();
+        #[cfg(not(feature = "std"))]
+        hint::spin_loop();
+        // `guard` still unlocks when it's dropped, so forget the new guard we
+        // get from relocking, or the lock would be unlocked twice.
+        core::mem::forget(guard.lock.lock());
+    }
+}
+
+impl<T: ?Sized> Deref for Guard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for Guard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: The very existence of this Guard
+        // guarantees we've exclusively locked the lock.
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for Guard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.unlock()
+    }
+}
+
+/// A guard for a part of the locked value, which only gives shared access.
+/// Created by [`Guard::map_ref`].
+///
+/// It's `Send` and `Sync` only if `U` is `Sync`, like a `&U`.
+pub struct MappedRefGuard<'a, U: ?Sized> {
+    locked: &'a AtomicBool,
+    #[cfg(feature = "std")]
+    sleepers: &'a AtomicU32,
+    #[cfg(all(debug_assertions, feature = "std"))]
+    owner: &'a AtomicU64,
+    value: &'a U,
+}
+
+impl<U: ?Sized> Deref for MappedRefGuard<'_, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        self.value
+    }
+}
+
+impl<U: ?Sized> Drop for MappedRefGuard<'_, U> {
+    fn drop(&mut self) {
+        #[cfg(all(debug_assertions, feature = "std"))]
+        self.owner.store(0, Ordering::Relaxed);
+        self.locked.store(false, Ordering::Release);
+        #[cfg(feature = "std")]
+        wake_sleeper(self.sleepers);
+    }
+}
+
+/// A guard that owns an `Arc` to its lock. Created by [`SpinLock::lock_arc`].
+pub struct ArcGuard<T: ?Sized> {
+    lock: Arc<SpinLock<T>>,
+}
+
+/// Same as for `Guard`.
+unsafe impl<T: ?Sized + Send> Send for ArcGuard<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for ArcGuard<T> {}
+
+impl<T: ?Sized> Deref for ArcGuard<T> {
     type Target = T;
 
     fn deref(&self) -> &T {
@@ -46,7 +615,7 @@ impl<T> Deref for Guard<'_, T> {
     }
 }
 
-impl<T> DerefMut for Guard<'_, T> {
+impl<T: ?Sized> DerefMut for ArcGuard<T> {
     fn deref_mut(&mut self) -> &mut T {
         // Safety: The very existence of this Guard
         // guarantees we've exclusively locked the lock.
@@ -54,16 +623,20 @@ impl<T> DerefMut for Guard<'_, T> {
     }
 }
 
-impl<T> Drop for Guard<'_, T> {
+impl<T: ?Sized> Drop for ArcGuard<T> {
     fn drop(&mut self) {
-        self.lock.locked.store(false, Ordering::Release)
+        // The `Arc` is only dropped after this, so the lock is still alive.
+        self.lock.unlock()
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(feature = "loom")))]
 mod tests {
-    use crate::spin_lock::SpinLock;
+    use crate::arc::Arc;
+    use crate::spin_lock::{ArcGuard, Guard, MappedRefGuard, SpinLock, TryLockError};
+    use std::panic::{self, AssertUnwindSafe};
     use std::thread;
+    use std::time::Duration;
 
     #[test]
     fn test() {
@@ -79,4 +652,372 @@ mod tests {
         let g = x.lock();
         assert!(g.as_slice() == [1, 2, 2] || g.as_slice() == [2, 2, 1]);
     }
+
+    #[test]
+    fn static_lock() {
+        static COUNTER: SpinLock<u32> = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1_000 {
+                        *COUNTER.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*COUNTER.lock(), 4_000);
+    }
+
+    #[test]
+    fn is_locked() {
+        let x = SpinLock::new(0);
+        assert!(!x.is_locked());
+        let g = x.lock();
+        assert!(x.is_locked());
+        drop(g);
+        assert!(!x.is_locked());
+    }
+
+    #[cfg(all(debug_assertions, feature = "std"))]
+    #[test]
+    #[should_panic(expected = "recursive lock on non-reentrant SpinLock")]
+    fn recursive_lock() {
+        let x = SpinLock::new(0);
+        let _g = x.lock();
+        let _h = x.lock();
+    }
+
+    #[test]
+    fn guards_are_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Guard<'_, Vec<i32>>>();
+        assert_send_sync::<ArcGuard<Vec<i32>>>();
+        assert_send_sync::<MappedRefGuard<'_, [i32]>>();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn sleeps_under_long_hold() {
+        use std::sync::atomic::Ordering;
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            let g = x.lock();
+            let waiter = s.spawn(|| *x.lock() += 1);
+            // The waiter soon gives up spinning, and goes to sleep.
+            while x.sleepers.load(Ordering::Relaxed) == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            thread::sleep(Duration::from_millis(10));
+            assert!(!waiter.is_finished());
+            drop(g);
+            waiter.join().unwrap();
+        });
+        assert_eq!(x.sleepers.load(Ordering::Relaxed), 0);
+        assert_eq!(*x.lock(), 1);
+    }
+
+    #[test]
+    fn oversubscribed() {
+        // Many more threads than cores, which all keep the lock for a while,
+        // so that the holder is often descheduled while others wait for it.
+        let threads = thread::available_parallelism().map_or(1, |n| n.get()) * 8;
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| {
+                    for _ in 0..200 {
+                        let mut g = x.lock();
+                        for _ in 0..100 {
+                            std::hint::spin_loop();
+                        }
+                        *g += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*x.lock(), threads * 200);
+    }
+
+    #[test]
+    fn update() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1_000 {
+                        x.update(|x| x + 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(*x.lock(), 4_000);
+    }
+
+    #[test]
+    fn with() {
+        let x = SpinLock::new(vec![1]);
+        let len = x.with(|v| {
+            v.push(2);
+            v.len()
+        });
+        assert_eq!(len, 2);
+        assert!(!x.is_locked());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| x.with(|_| panic!())));
+        assert!(result.is_err());
+        assert!(!x.is_locked());
+        assert_eq!(*x.lock(), [1, 2]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn lock_with_recovery() {
+        let x = SpinLock::new(vec![1, 2]);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            x.lock_with_recovery(
+                |v| {
+                    v.clear();
+                    panic!("interrupted halfway");
+                },
+                |v| {
+                    // The lock is still held while repairing.
+                    assert!(v.is_empty());
+                    v.extend([1, 2]);
+                },
+            )
+        }));
+        assert!(result.is_err());
+        assert!(!x.is_locked());
+        assert_eq!(*x.lock(), [1, 2]);
+        assert_eq!(x.lock_with_recovery(|v| v.len(), |_| unreachable!()), 2);
+    }
+
+    #[test]
+    fn from_box() {
+        let lock: Box<SpinLock<[i32]>> = SpinLock::from_box(vec![0; 4].into_boxed_slice());
+        thread::scope(|s| {
+            for i in 0..4 {
+                let lock = &lock;
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        let mut g = lock.lock();
+                        g[i] += 1;
+                        g[(i + 1) % 4] += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*lock.lock(), [2000; 4]);
+
+        let lock: Box<SpinLock<[u64]>> = SpinLock::from_box(Box::new([]));
+        assert!(lock.lock().is_empty());
+        // The value is dropped along with the lock.
+        let lock: Box<SpinLock<[String]>> = SpinLock::from_box(vec!["a".to_string()].into());
+        assert_eq!(lock.lock()[0], "a");
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn from_box_unsized() {
+        let lock: Box<SpinLock<str>> = SpinLock::from_box("hello".into());
+        assert_eq!(&*lock.lock(), "hello");
+        let lock: Box<SpinLock<dyn Fn() -> i32>> = SpinLock::from_box(Box::new(|| 42));
+        assert_eq!(lock.lock()(), 42);
+    }
+
+    #[test]
+    fn try_lock() {
+        let x = SpinLock::new(0);
+        let g = x.try_lock().unwrap();
+        assert!(matches!(x.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(g);
+        assert!(x.try_lock().is_ok());
+    }
+
+    #[test]
+    fn try_lock_for() {
+        let x = SpinLock::new(0);
+        let g = x.lock();
+        assert!(x.try_lock_for(10).is_none());
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                drop(g);
+            });
+            *x.try_lock_for(u32::MAX).unwrap() += 1;
+        });
+        assert_eq!(*x.lock(), 1);
+    }
+
+    #[test]
+    fn guard_lock() {
+        let x = SpinLock::new(0);
+        let g = x.lock();
+        let lock = Guard::lock(&g);
+        assert!(std::ptr::eq(lock, &x));
+        assert!(lock.is_locked());
+        drop(g);
+        assert!(!lock.is_locked());
+    }
+
+    #[test]
+    fn bump() {
+        let x = SpinLock::new(vec![1]);
+        thread::scope(|s| {
+            let mut g = x.lock();
+            s.spawn(|| x.lock().push(2));
+            // Keep bumping until the other thread got its turn.
+            while g.len() == 1 {
+                Guard::bump(&mut g);
+            }
+            g.push(3);
+        });
+        assert_eq!(*x.lock(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn lock_arc() {
+        let x = Arc::new(SpinLock::new(vec![1]));
+        let mut g = SpinLock::lock_arc(&x);
+        g.push(2);
+        thread::spawn(move || {
+            g.push(3);
+            drop(g);
+        })
+        .join()
+        .unwrap();
+        assert!(!x.is_locked());
+        assert_eq!(*x.lock(), [1, 2, 3]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn lock_arc_moved_away() {
+        // Locking again while a guard from `lock_arc` is held on another
+        // thread isn't a recursive lock, and waits for that thread instead.
+        let x = Arc::new(SpinLock::new(0));
+        let g = SpinLock::lock_arc(&x);
+        let t = thread::spawn(move || {
+            let mut g = g;
+            // Long enough for the lock below to give up spinning.
+            thread::sleep(Duration::from_millis(20));
+            *g += 1;
+        });
+        *x.lock() += 1;
+        t.join().unwrap();
+        assert_eq!(*x.lock(), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn guard_moved_away() {
+        // Locking again while a guard that was sent to another thread is still
+        // held there isn't a recursive lock, and waits for that thread.
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            let mut g = x.lock();
+            s.spawn(move || {
+                // Long enough for the lock below to give up spinning.
+                thread::sleep(Duration::from_millis(20));
+                *g += 1;
+            });
+            *x.lock() += 1;
+        });
+        assert_eq!(*x.lock(), 2);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1_000 {
+                        let mut g = x.lock();
+                        // Give the other threads a chance to run into the
+                        // held lock.
+                        thread::yield_now();
+                        *g += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.acquire_count(), 4_000);
+        assert!(x.spin_count() > 0);
+    }
+
+    #[test]
+    fn map_ref() {
+        let x = SpinLock::new((String::from("name"), 1));
+        let name = Guard::map_ref(x.lock(), |(name, _)| name.as_str());
+        assert_eq!(&*name, "name");
+        assert!(x.try_lock().is_err());
+        drop(name);
+        assert!(!x.is_locked());
+        x.lock().1 += 1;
+        assert_eq!(x.lock().1, 2);
+    }
+
+    #[test]
+    fn default() {
+        let x: SpinLock<Vec<i32>> = SpinLock::default();
+        assert!(x.lock().is_empty());
+    }
+
+    #[test]
+    fn debug() {
+        let x = SpinLock::new(vec![1, 2]);
+        assert_eq!(format!("{x:?}"), "SpinLock { data: [1, 2] }");
+        let _g = x.lock();
+        assert_eq!(format!("{x:?}"), "SpinLock { data: <locked> }");
+    }
+}
+
+/// Model tests, which loom runs under every possible interleaving of the
+/// threads, with the `loom` feature.
+#[cfg(all(test, feature = "loom"))]
+mod loom_tests {
+    use crate::arc::Arc;
+    use crate::spin_lock::SpinLock;
+    use loom::cell::UnsafeCell;
+    use loom::thread;
+
+    #[test]
+    fn mutual_exclusion() {
+        loom::model(|| {
+            // Loom catches any two accesses to the counter that the lock
+            // doesn't order.
+            let lock = Arc::new(SpinLock::new(UnsafeCell::new(0)));
+            let threads: Vec<_> = (0..2)
+                .map(|_| {
+                    let lock = lock.clone();
+                    thread::spawn(move || {
+                        let guard = lock.lock();
+                        guard.with_mut(|counter| unsafe { *counter += 1 });
+                    })
+                })
+                .collect();
+            for t in threads {
+                t.join().unwrap();
+            }
+            assert_eq!(lock.lock().with(|counter| unsafe { *counter }), 2);
+        });
+    }
+
+    #[test]
+    fn try_lock() {
+        loom::model(|| {
+            let lock = Arc::new(SpinLock::new(UnsafeCell::new(0)));
+            let lock2 = lock.clone();
+            let t = thread::spawn(move || {
+                if let Ok(guard) = lock2.try_lock() {
+                    guard.with_mut(|counter| unsafe { *counter += 1 });
+                }
+            });
+            lock.lock().with_mut(|counter| unsafe { *counter += 1 });
+            t.join().unwrap();
+            let count = lock.lock().with(|counter| unsafe { *counter });
+            assert!(count == 1 || count == 2);
+        });
+    }
 }
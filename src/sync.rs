@@ -0,0 +1,64 @@
+//! The atomics, hints and threads that `Arc`, `SpinLock` and `one_shot` are
+//! built on, in one place, so that the `loom` feature can swap them for
+//! loom's instrumented versions. Loom then runs the model tests under every
+//! possible interleaving of the primitives' atomic operations.
+//!
+//! `UnsafeCell` is not swapped: loom's version can only be accessed through
+//! closures, which doesn't fit guards that hand out plain references. The
+//! model tests put loom's cells inside the primitives instead, so loom still
+//! checks that the primitives order all accesses to the values they protect.
+//!
+//! In debug builds with the `ordering-checks` feature, the atomics are swapped
+//! for the ones in [`crate::ordering_checks`] instead, which track
+//! happens-before relationships while the regular tests run.
+
+#[cfg(all(feature = "ordering-checks", debug_assertions, not(feature = "loom")))]
+pub(crate) use crate::ordering_checks::atomic;
+#[cfg(not(feature = "loom"))]
+pub(crate) use core::hint;
+#[cfg(not(any(feature = "loom", all(feature = "ordering-checks", debug_assertions))))]
+pub(crate) use core::sync::atomic;
+#[cfg(feature = "loom")]
+pub(crate) use loom::{hint, sync::atomic, thread, thread_local};
+#[cfg(all(feature = "std", not(feature = "loom")))]
+pub(crate) use std::{thread, thread_local};
+
+/// Like `AtomicBool::swap`.
+///
+/// A swap that leaves the flag unchanged still stores to it, and loom can't
+/// always tell whether that store came before or after one by another thread
+/// that hasn't seen it yet. It may then let later operations read the older of
+/// the two, which no real execution can, and report bugs that don't exist. So
+/// with loom, this is a compare-and-exchange, which stores nothing if it fails.
+pub(crate) fn swap(flag: &atomic::AtomicBool, value: bool, ordering: atomic::Ordering) -> bool {
+    if cfg!(feature = "loom") {
+        let failure = match ordering {
+            atomic::Ordering::Release => atomic::Ordering::Relaxed,
+            atomic::Ordering::AcqRel => atomic::Ordering::Acquire,
+            ordering => ordering,
+        };
+        match flag.compare_exchange(!value, value, ordering, failure) {
+            Ok(old) | Err(old) => old,
+        }
+    } else {
+        flag.swap(value, ordering)
+    }
+}
+
+/// Declares `const fn`s, which aren't `const` with loom, whose atomics can't
+/// be created in constant expressions.
+macro_rules! const_fn {
+    ($($(#[$attr:meta])* $vis:vis const fn $name:ident $args:tt -> $ret:ty $body:block)*) => {
+        $(
+            $(#[$attr])*
+            #[cfg(not(feature = "loom"))]
+            $vis const fn $name $args -> $ret $body
+
+            $(#[$attr])*
+            #[cfg(feature = "loom")]
+            $vis fn $name $args -> $ret $body
+        )*
+    };
+}
+
+pub(crate) use const_fn;
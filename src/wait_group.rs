@@ -0,0 +1,120 @@
+use crate::futex::{wait, wake_all};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Waits for a dynamic number of tasks to finish.
+///
+/// Every task is announced with `add` (or `guard`) and finished with `done`
+/// (or by dropping the guard), and `wait` blocks until none are left.
+pub struct WaitGroup {
+    count: AtomicUsize,
+}
+
+impl WaitGroup {
+    pub const fn new() -> Self {
+        Self {
+            count: AtomicUsize::new(0),
+        }
+    }
+
+    /// Announces `n` more tasks.
+    pub fn add(&self, n: usize) {
+        if self.count.fetch_add(n, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+    }
+
+    /// Marks one task as done.
+    ///
+    /// Panics if there are no tasks left to finish.
+    pub fn done(&self) {
+        // Release, so the waiting thread sees everything the task did.
+        let n = self
+            .count
+            .fetch_update(Ordering::Release, Ordering::Relaxed, |n| n.checked_sub(1))
+            .expect("done called more often than add");
+        if n == 1 {
+            wake_all(&self.count);
+        }
+    }
+
+    /// Announces one more task, which is done when the returned guard is
+    /// dropped.
+    pub fn guard(&self) -> WaitGroupGuard<'_> {
+        self.add(1);
+        WaitGroupGuard { wait_group: self }
+    }
+
+    /// Blocks until all tasks are done, returning right away if there are
+    /// none.
+    ///
+    /// Tasks that are added after the count dropped to zero are not waited
+    /// for, if this already returned by then.
+    pub fn wait(&self) {
+        loop {
+            let n = self.count.load(Ordering::Acquire);
+            if n == 0 {
+                return;
+            }
+            // Only the last `done` wakes us up, but if the count changes
+            // before we're asleep, we don't go to sleep and check again.
+            wait(&self.count, n);
+        }
+    }
+}
+
+impl Default for WaitGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct WaitGroupGuard<'a> {
+    wait_group: &'a WaitGroup,
+}
+
+impl Drop for WaitGroupGuard<'_> {
+    fn drop(&mut self) {
+        self.wait_group.done();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wait_group::WaitGroup;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test() {
+        let wait_group = WaitGroup::new();
+        let finished = AtomicUsize::new(0);
+        // Nothing to wait for yet.
+        wait_group.wait();
+        thread::scope(|s| {
+            for i in 0..8 {
+                let guard = wait_group.guard();
+                let finished = &finished;
+                s.spawn(move || {
+                    thread::sleep(Duration::from_millis(i * 5));
+                    finished.fetch_add(1, Ordering::Relaxed);
+                    drop(guard);
+                });
+            }
+            wait_group.wait();
+            assert_eq!(finished.load(Ordering::Relaxed), 8);
+        });
+    }
+
+    #[test]
+    fn add_done() {
+        let wait_group = WaitGroup::new();
+        wait_group.add(2);
+        thread::scope(|s| {
+            s.spawn(|| wait_group.done());
+            s.spawn(|| wait_group.done());
+            wait_group.wait();
+        });
+        wait_group.wait();
+    }
+}
@@ -0,0 +1,78 @@
+use crate::arc::{Arc, Weak};
+use crate::spin_lock::SpinLock;
+
+/// A lazily created instance that only lives while it is in use.
+///
+/// The singleton keeps just a weak pointer to the instance. Once every `Arc`
+/// handed out by [`get`](Self::get) is dropped, the instance is dropped too,
+/// and the next `get` creates a new one using the factory.
+pub struct WeakSingleton<T, F: Fn() -> T> {
+    instance: SpinLock<Option<Weak<T>>>,
+    factory: F,
+}
+
+impl<T, F: Fn() -> T> WeakSingleton<T, F> {
+    pub fn new(factory: F) -> Self {
+        Self {
+            instance: SpinLock::new(None),
+            factory,
+        }
+    }
+
+    /// Returns the current instance, or creates a new one if there is none.
+    ///
+    /// The factory runs while the lock is held, so concurrent callers wait
+    /// for it and then share the new instance.
+    pub fn get(&self) -> Arc<T> {
+        let mut instance = self.instance.lock();
+        if let Some(arc) = instance.as_ref().and_then(Weak::upgrade) {
+            return arc;
+        }
+        let arc = Arc::new((self.factory)());
+        *instance = Some(Arc::downgrade(&arc));
+        arc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::weak_singleton::WeakSingleton;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test() {
+        static NUM_CREATED: AtomicUsize = AtomicUsize::new(0);
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Instance;
+        impl Drop for Instance {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let singleton = WeakSingleton::new(|| {
+            NUM_CREATED.fetch_add(1, Ordering::Relaxed);
+            Instance
+        });
+
+        let (a, b) = thread::scope(|s| {
+            let a = s.spawn(|| singleton.get());
+            let b = s.spawn(|| singleton.get());
+            (a.join().unwrap(), b.join().unwrap())
+        });
+        // Both threads share the same instance.
+        assert!(std::ptr::eq(&*a, &*b));
+        assert_eq!(NUM_CREATED.load(Ordering::Relaxed), 1);
+        let first = &*a as *const Instance;
+        drop(a);
+        drop(b);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+
+        // The next get creates a fresh instance.
+        let c = singleton.get();
+        assert_eq!(NUM_CREATED.load(Ordering::Relaxed), 2);
+        assert!(!std::ptr::eq(&*c, first));
+        drop(c);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,70 @@
+//! Tests that need to control the global allocator. It is shared by every test
+//! in a binary, so these get one of their own.
+
+#![cfg(not(feature = "loom"))]
+
+use rust_atomics::arc::{AllocError, Arc};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Lets tests make allocations on their own thread fail, and count how many of
+/// them are live.
+struct FailingAlloc;
+
+thread_local! {
+    static FAIL_ALLOC: Cell<bool> = const { Cell::new(false) };
+    static LIVE_ALLOCS: Cell<isize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for FailingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if FAIL_ALLOC.with(Cell::get) {
+            return std::ptr::null_mut();
+        }
+        let _ = LIVE_ALLOCS.try_with(|live| live.set(live.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = LIVE_ALLOCS.try_with(|live| live.set(live.get() - 1));
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: FailingAlloc = FailingAlloc;
+
+#[test]
+fn try_new() {
+    static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+    struct DetectDrop;
+    impl Drop for DetectDrop {
+        fn drop(&mut self) {
+            NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    let x = Arc::try_new(DetectDrop).unwrap();
+    drop(x);
+    assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+
+    FAIL_ALLOC.with(|fail| fail.set(true));
+    let result = Arc::try_new(DetectDrop);
+    FAIL_ALLOC.with(|fail| fail.set(false));
+    assert_eq!(result.err(), Some(AllocError));
+    // The value was dropped, not leaked.
+    assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn from_box_slice() {
+    let boxed: Box<[String]> = vec![String::from("a"), String::from("bc")].into_boxed_slice();
+    let buffers: Vec<_> = boxed.iter().map(|s| (s.as_ptr(), s.capacity())).collect();
+    let live = LIVE_ALLOCS.with(Cell::get);
+    let x: Arc<[String]> = Arc::from(boxed);
+    // The `Arc` was allocated, and the box freed, but the strings were moved
+    // rather than cloned.
+    assert_eq!(LIVE_ALLOCS.with(Cell::get), live);
+    assert!(x.iter().map(|s| (s.as_ptr(), s.capacity())).eq(buffers));
+    assert_eq!(*x, ["a", "bc"]);
+}